@@ -0,0 +1,119 @@
+use battery_spec_test::energy_components::charging_curves::ChargingCurve;
+use battery_spec_test::energy_components::ev_chargers::{Charger, ChargerCatalog, Connector};
+use battery_spec_test::energy_components::ev_sessions::{
+    sample_vehicles, EvSession, SessionSimulator, VehicleProfile,
+};
+use battery_spec_test::ocpp::central_system::CentralSystem;
+use battery_spec_test::ocpp::charge_point::ChargePointSimulator;
+use battery_spec_test::time_processes::sample_arrival_times;
+use battery_spec_test::write_demand_csv;
+use std::env;
+
+const USAGE: &str = "usage: ocpp_replay [local | replay <central system url> | serve <addr>]";
+
+/// Simulates a day of public charging on a small mixed fleet.
+async fn simulate() -> Result<(Vec<Charger>, Vec<EvSession>), anyhow::Error> {
+    let start_date = "2024-06-03 00:00:00+0000";
+    let end_date = "2024-06-04 00:00:00+0000";
+    let catalog = ChargerCatalog::from_file("chargers.json")?;
+    let mut chargers = catalog.build_fleet(&[
+        ("dcfc-150kw".to_string(), 4),
+        ("level2-19kw".to_string(), 4),
+    ])?;
+    let profiles = vec![VehicleProfile {
+        name: "sedan".to_string(),
+        weight: 1.0,
+        battery_capacity_wh: 75000.0,
+        max_acceptance_w: 150000.0,
+        charging_curve: Some(ChargingCurve::generic_400v()),
+        arrival_soc: (0.1, 0.4),
+        target_soc: 0.8,
+        dwell_minutes: (20, 90),
+        connectors: vec![Connector::Ccs1, Connector::J1772],
+        v2g_capable: false,
+    }];
+    let arrivals = sample_arrival_times(start_date, end_date, vec![2.0; 24]).await?;
+    let vehicles = sample_vehicles(&arrivals, &profiles);
+    let sessions = SessionSimulator::new(start_date, end_date)?.run(&mut chargers, &vehicles);
+    Ok((chargers, sessions))
+}
+
+/// Replays every charger at once against `url`.
+async fn replay(url: &str, chargers: &[Charger], sessions: &[EvSession]) -> anyhow::Result<()> {
+    let mut tasks = Vec::new();
+    for (charger_idx, charger) in chargers.iter().enumerate() {
+        let simulator = ChargePointSimulator::from_run(
+            &format!("CP{:03}", charger_idx + 1),
+            charger,
+            charger_idx,
+            sessions,
+        );
+        let url = url.to_string();
+        tasks.push(tokio::spawn(async move { simulator.run(&url).await }));
+    }
+    for task in tasks {
+        task.await??;
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        None | Some("local") => {
+            // Round trip through a local central system and compare what it metered with
+            // the AC input of the simulated sessions.
+            let (chargers, sessions) = simulate().await?;
+            let central_system = CentralSystem::bind("127.0.0.1:0").await?;
+            let url = format!("ws://{}/ocpp", central_system.local_addr()?);
+            let log = central_system.log();
+            let server = tokio::spawn(central_system.serve());
+            replay(&url, &chargers, &sessions).await?;
+            server.abort();
+            let demand = log.lock().unwrap().demand_w_ts();
+            let simulated_wh: f32 = sessions
+                .iter()
+                .filter_map(|session| {
+                    let charger = &chargers[session.charger_index?];
+                    Some(session.energy_delivered_wh / charger.efficiency_at_nominal_power)
+                })
+                .sum();
+            let metered_wh: f32 = demand.iter().map(|(_, w)| w).sum();
+            println!(
+                "replayed {} sessions; metered {:.1} kWh against {:.1} kWh simulated",
+                sessions
+                    .iter()
+                    .filter(|session| session.plug_in.is_some())
+                    .count(),
+                metered_wh / 1000.0,
+                simulated_wh / 1000.0
+            );
+            write_demand_csv(&demand, "ocpp_demand.csv")?;
+        }
+        Some("replay") => {
+            let url = args.get(2).ok_or(USAGE)?;
+            let (chargers, sessions) = simulate().await?;
+            replay(url, &chargers, &sessions).await?;
+        }
+        Some("serve") => {
+            // Ingest a real charge point's MeterValues until interrupted.
+            let addr = args.get(2).map_or("0.0.0.0:9000", String::as_str);
+            let central_system = CentralSystem::bind(addr).await?;
+            println!(
+                "central system listening on ws://{}",
+                central_system.local_addr()?
+            );
+            let log = central_system.log();
+            tokio::select! {
+                result = central_system.serve() => result?,
+                _ = tokio::signal::ctrl_c() => {}
+            }
+            let demand = log.lock().unwrap().demand_w_ts();
+            write_demand_csv(&demand, "ocpp_demand.csv")?;
+            println!("wrote {} hours of demand to ocpp_demand.csv", demand.len());
+        }
+        Some(_) => return Err(USAGE.into()),
+    }
+    Ok(())
+}
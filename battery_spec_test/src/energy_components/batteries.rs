@@ -1,15 +1,21 @@
 use crate::energy_components::ev_chargers::Charger;
 use crate::energy_components::general_fun::EnergyConsumer;
 use crate::Utc;
-use chrono::DateTime;
+use chrono::{DateTime, TimeDelta};
 
+use super::dispatch::{DispatchContext, DispatchStrategy, SelfConsumption};
 use super::general_fun::PowerComponent;
+use degradation::{DegradationModel, DegradationTracker};
 #[derive(Clone, Debug)]
 pub struct BatteryPowerComponent {
     pub storage: Option<Vec<(DateTime<Utc>, f32)>>,
     pub input_power_w_ts: Option<Vec<(DateTime<Utc>, f32)>>,
     pub output_power_w_ts: Option<Vec<(DateTime<Utc>, f32)>>,
     pub neg_stat_ts: Option<Vec<(DateTime<Utc>, bool)>>,
+    pub grid_import_w_ts: Option<Vec<(DateTime<Utc>, f32)>>,
+    pub curtailment_w_ts: Option<Vec<(DateTime<Utc>, f32)>>,
+    pub loss_wh_ts: Option<Vec<(DateTime<Utc>, f32)>>,
+    pub state_of_health_ts: Option<Vec<(DateTime<Utc>, f32)>>,
 }
 #[derive(Clone, Debug)]
 pub struct BatteryStorage {
@@ -18,11 +24,19 @@ pub struct BatteryStorage {
     pub depth_of_discharge: f32,
     pub battery_system_voltage: f32,
     pub efficiency: f32,
+    pub charge_efficiency: f32,
+    pub discharge_efficiency: f32,
+    pub max_charge_power_w: Option<f32>,
+    pub max_discharge_power_w: Option<f32>,
+    pub degradation: Option<DegradationModel>,
+    /// Sits on the PV array's DC bus and charges from power the inverter clips.
+    pub dc_coupled: bool,
     pub battery_state: BatteryPowerComponent,
 }
 
 impl BatteryStorage {
-    // General constructor
+    // General constructor. `efficiency` is the round-trip efficiency in percent and is split
+    // evenly between charging and discharging.
     pub fn new(
         capacity: f32,
         watt_hours: f32,
@@ -36,20 +50,104 @@ impl BatteryStorage {
             depth_of_discharge,
             battery_system_voltage,
             efficiency,
+            charge_efficiency: (efficiency / 100.0).sqrt() * 100.0,
+            discharge_efficiency: (efficiency / 100.0).sqrt() * 100.0,
+            max_charge_power_w: None,
+            max_discharge_power_w: None,
+            degradation: None,
+            dc_coupled: false,
 
             battery_state: BatteryPowerComponent {
                 storage: None,
                 input_power_w_ts: None,
                 output_power_w_ts: None,
                 neg_stat_ts: None,
+                grid_import_w_ts: None,
+                curtailment_w_ts: None,
+                loss_wh_ts: None,
+                state_of_health_ts: None,
             },
         }
     }
 
+    /// Overrides the symmetric split of `efficiency` with separate one-way efficiencies, in
+    /// percent. The round-trip `efficiency` is kept in step with the two.
+    pub fn with_efficiencies(mut self, charge_efficiency: f32, discharge_efficiency: f32) -> Self {
+        self.charge_efficiency = charge_efficiency;
+        self.discharge_efficiency = discharge_efficiency;
+        self.efficiency = charge_efficiency * discharge_efficiency / 100.0;
+        self
+    }
+
+    /// Caps the power the battery can absorb and deliver, in watts, as seen from the AC side.
+    pub fn with_power_limits(
+        mut self,
+        max_charge_power_w: f32,
+        max_discharge_power_w: f32,
+    ) -> Self {
+        self.max_charge_power_w = Some(max_charge_power_w);
+        self.max_discharge_power_w = Some(max_discharge_power_w);
+        self
+    }
+
+    /// Caps charge and discharge power at `c_rate` times the full capacity per hour.
+    pub fn with_c_rate(self, c_rate: f32) -> Self {
+        let limit = c_rate * self.full_capacity_wh();
+        self.with_power_limits(limit, limit)
+    }
+
+    /// Fades the effective capacity over the simulation according to `model`.
+    pub fn with_degradation(mut self, model: DegradationModel) -> Self {
+        self.degradation = Some(model);
+        self
+    }
+
+    pub fn with_dc_coupling(mut self) -> Self {
+        self.dc_coupled = true;
+        self
+    }
+
+    /// Energy held when the battery is full, in watt hours.
+    pub fn full_capacity_wh(&self) -> f32 {
+        self.capacity * self.watt_hours
+    }
+
+    /// Lowest state of charge the depth of discharge allows, in watt hours.
+    pub fn min_storage_wh(&self) -> f32 {
+        self.full_capacity_wh() * (1.0 - self.depth_of_discharge / 100.0)
+    }
+
+    /// Energy available between the depth of discharge floor and full capacity.
+    pub fn usable_capacity_wh(&self) -> f32 {
+        self.full_capacity_wh() - self.min_storage_wh()
+    }
+
+    /// Self-consumption dispatch: PV surplus is stored and deficits are drawn from the battery.
     pub fn update_power_component(
+        self,
+        generator: PowerComponent,
+        utility: PowerComponent,
+    ) -> BatteryStorage {
+        self.dispatch(generator, utility, &[], None, &SelfConsumption)
+    }
+
+    /// Steps the state of charge through the generator and utility series one hour at a time,
+    /// letting `strategy` pick the battery setpoint at each step. The battery starts at its
+    /// depth of discharge floor and stays between that floor and full capacity, and the grid
+    /// covers the rest: net import is recorded as grid import and net surplus as curtailment.
+    /// Energy lost to charge and discharge inefficiency is accumulated in `loss_wh_ts`. With a
+    /// degradation model the capacity bounds shrink with the state of health, which is recorded
+    /// in `state_of_health_ts` (percent, 100 without a model). A DC-coupled battery first
+    /// charges from the hour's `clipped_w_ts`, which never reaches the AC bus, and discharges
+    /// through the array's inverter, so its output plus the generation stays within
+    /// `inverter_ac_rating_w`.
+    pub fn dispatch(
         mut self,
         generator: PowerComponent,
         utility: PowerComponent,
+        clipped_w_ts: &[(DateTime<Utc>, f32)],
+        inverter_ac_rating_w: Option<f32>,
+        strategy: &dyn DispatchStrategy,
     ) -> BatteryStorage {
         let temp_vals = generator
             .output_power_w_ts
@@ -59,33 +157,388 @@ impl BatteryStorage {
             .map(|(&(d1, g1), &(_d2, o1))| (d1, g1, o1))
             .collect::<Vec<(DateTime<Utc>, f32, f32)>>();
 
+        let charge_eff = self.charge_efficiency / 100.0;
+        let discharge_eff = self.discharge_efficiency / 100.0;
+        let max_charge = self.max_charge_power_w.unwrap_or(f32::INFINITY);
+        let max_discharge = self.max_discharge_power_w.unwrap_or(f32::INFINITY);
         let mut storage: Vec<(DateTime<Utc>, f32)> = Vec::new();
         let mut input: Vec<(DateTime<Utc>, f32)> = Vec::new();
         let mut output: Vec<(DateTime<Utc>, f32)> = Vec::new();
         let mut neg_dummy: Vec<(DateTime<Utc>, bool)> = Vec::new();
-        storage.push((temp_vals[0].0, temp_vals[0].1 - temp_vals[0].2));
-        input.push((temp_vals[0].0, temp_vals[0].1));
-        output.push((temp_vals[0].0, temp_vals[0].2));
-        neg_dummy.push((temp_vals[0].0, storage[0].1 < 0.0));
-        for idx in 1..temp_vals.len() {
-            let temp_storage = storage[idx - 1].1 + temp_vals[idx].1 - temp_vals[idx].2;
-            if self.capacity * self.watt_hours <= temp_storage {
-                storage.push((temp_vals[idx].0, self.capacity * self.watt_hours));
+        let mut grid_import: Vec<(DateTime<Utc>, f32)> = Vec::new();
+        let mut curtailment: Vec<(DateTime<Utc>, f32)> = Vec::new();
+        let mut losses: Vec<(DateTime<Utc>, f32)> = Vec::new();
+        let mut health: Vec<(DateTime<Utc>, f32)> = Vec::new();
+        let mut tracker = self
+            .degradation
+            .clone()
+            .map(|model| DegradationTracker::new(model, self.full_capacity_wh()));
+        let mut prev_storage = self.min_storage_wh();
+        let mut total_loss = 0.0;
+        for (idx, &(date, generated, demand)) in temp_vals.iter().enumerate() {
+            let soh = match tracker.as_mut() {
+                Some(tracker) => tracker.update(date, prev_storage),
+                None => 100.0,
+            };
+            let max_storage = self.full_capacity_wh() * soh / 100.0;
+            let min_storage = max_storage * (1.0 - self.depth_of_discharge / 100.0);
+            let mut prev_storage_bounded = prev_storage.min(max_storage);
+            let mut max_charge = max_charge;
+            let mut max_discharge = max_discharge;
+            if self.dc_coupled {
+                let clipped = clipped_w_ts.get(idx).map_or(0.0, |&(_, w)| w);
+                let absorbed = clipped
+                    .min(max_charge)
+                    .min((max_storage - prev_storage_bounded).max(0.0) / charge_eff);
+                total_loss += absorbed * (1.0 - charge_eff);
+                prev_storage_bounded += absorbed * charge_eff;
+                max_charge -= absorbed;
+                if let Some(ac_rating_w) = inverter_ac_rating_w {
+                    max_discharge = max_discharge.min((ac_rating_w - generated.max(0.0)).max(0.0));
+                }
+            }
+            let setpoint = strategy.battery_setpoint_w(&DispatchContext {
+                date,
+                generation_w: generated,
+                demand_w: demand,
+                storage_wh: prev_storage_bounded,
+                min_storage_wh: min_storage,
+                max_storage_wh: max_storage,
+                discharge_efficiency: discharge_eff,
+            });
+            let (next_storage, battery_w) = if setpoint >= 0.0 {
+                // Charging: only `charge_eff` of what goes in ends up stored.
+                let accepted = setpoint
+                    .min(max_charge)
+                    .min((max_storage - prev_storage_bounded).max(0.0) / charge_eff);
+                total_loss += accepted * (1.0 - charge_eff);
+                (prev_storage_bounded + accepted * charge_eff, accepted)
             } else {
-                storage.push((temp_vals[idx].0, temp_storage));
+                // Discharging: covering `delivered` drains `delivered / discharge_eff`.
+                let delivered = (-setpoint)
+                    .min(max_discharge)
+                    .min((prev_storage_bounded - min_storage).max(0.0) * discharge_eff);
+                total_loss += delivered / discharge_eff - delivered;
+                (prev_storage_bounded - delivered / discharge_eff, -delivered)
             };
-            input.push((temp_vals[idx].0, temp_vals[idx].1));
-            output.push((temp_vals[idx].0, temp_vals[idx].2));
-            let is_negative = storage[idx].1 < 0.0;
-            neg_dummy.push((temp_vals[idx].0, is_negative));
+            let grid_w = battery_w - (generated - demand);
+            let (shortfall, surplus) = (grid_w.max(0.0), (-grid_w).max(0.0));
+            storage.push((date, next_storage));
+            input.push((date, generated));
+            output.push((date, demand));
+            neg_dummy.push((date, shortfall > 0.0));
+            grid_import.push((date, shortfall));
+            curtailment.push((date, surplus));
+            losses.push((date, total_loss));
+            health.push((date, soh));
+            prev_storage = next_storage;
         }
 
         self.battery_state.storage = Some(storage);
         self.battery_state.input_power_w_ts = Some(input);
         self.battery_state.output_power_w_ts = Some(output);
         self.battery_state.neg_stat_ts = Some(neg_dummy);
+        self.battery_state.grid_import_w_ts = Some(grid_import);
+        self.battery_state.curtailment_w_ts = Some(curtailment);
+        self.battery_state.loss_wh_ts = Some(losses);
+        self.battery_state.state_of_health_ts = Some(health);
         self
     }
+
+    /// Date the state of health reaches the degradation model's end-of-life threshold. When the
+    /// simulation ends first, the average fade rate over the run is extrapolated from its start.
+    pub fn end_of_life_date(&self) -> Option<DateTime<Utc>> {
+        let model = self.degradation.as_ref()?;
+        let health = self.battery_state.state_of_health_ts.as_ref()?;
+        if let Some(&(date, _)) = health.iter().find(|(_, soh)| *soh <= model.end_of_life_soh) {
+            return Some(date);
+        }
+        let (start, _) = *health.first()?;
+        let (end, soh_end) = *health.last()?;
+        let elapsed_hours = (end - start).num_hours() as f32;
+        let faded = 100.0 - soh_end;
+        if elapsed_hours <= 0.0 || faded <= 0.0 {
+            return None;
+        }
+        let hours_to_eol = (100.0 - model.end_of_life_soh) / faded * elapsed_hours;
+        start.checked_add_signed(TimeDelta::try_hours(hours_to_eol as i64)?)
+    }
+}
+
+pub mod degradation {
+    use chrono::{DateTime, Utc};
+
+    const HOURS_PER_YEAR: f32 = 8760.0;
+
+    /// Capacity fade from cycling and calendar aging. Cycle damage follows Miner's rule over the
+    /// rainflow-counted cycles, so a damage of 1.0 brings the battery to `end_of_life_soh`.
+    #[derive(Clone, Debug)]
+    pub struct DegradationModel {
+        /// (depth of discharge in percent, cycles to end of life), sorted by depth.
+        pub cycle_life_curve: Vec<(f32, f32)>,
+        /// Capacity lost per year of age, in percent of nominal capacity.
+        pub calendar_fade_per_year: f32,
+        /// State of health, in percent, at which the battery is considered spent.
+        pub end_of_life_soh: f32,
+    }
+
+    impl DegradationModel {
+        pub fn new(
+            mut cycle_life_curve: Vec<(f32, f32)>,
+            calendar_fade_per_year: f32,
+            end_of_life_soh: f32,
+        ) -> Self {
+            cycle_life_curve.sort_by(|a, b| a.0.total_cmp(&b.0));
+            Self {
+                cycle_life_curve,
+                calendar_fade_per_year,
+                end_of_life_soh,
+            }
+        }
+
+        /// Typical figures for a stationary lithium iron phosphate pack.
+        pub fn lithium_iron_phosphate() -> Self {
+            Self::new(
+                vec![
+                    (10.0, 20000.0),
+                    (20.0, 12000.0),
+                    (50.0, 6000.0),
+                    (80.0, 4000.0),
+                    (100.0, 3000.0),
+                ],
+                1.0,
+                80.0,
+            )
+        }
+
+        /// Miner's-rule damage of `count` cycles at `depth` percent depth of discharge. Below
+        /// the shallowest point of the curve the damage scales linearly with depth.
+        pub fn cycle_damage(&self, depth: f32, count: f32) -> f32 {
+            let curve = &self.cycle_life_curve;
+            if depth <= 0.0 || curve.is_empty() {
+                return 0.0;
+            }
+            let (first_depth, first_cycles) = curve[0];
+            let (last_depth, last_cycles) = curve[curve.len() - 1];
+            let cycles = if depth <= first_depth {
+                return count / first_cycles * depth / first_depth;
+            } else if depth >= last_depth {
+                last_cycles
+            } else {
+                let idx = curve.iter().position(|(d, _)| *d >= depth).unwrap();
+                let (d0, n0) = curve[idx - 1];
+                let (d1, n1) = curve[idx];
+                n0 + (n1 - n0) * (depth - d0) / (d1 - d0)
+            };
+            count / cycles
+        }
+
+        /// Capacity fade, in percent, after `hours` of age with accumulated cycle `damage`.
+        pub fn fade(&self, damage: f32, hours: f32) -> f32 {
+            damage * (100.0 - self.end_of_life_soh)
+                + self.calendar_fade_per_year * hours / HOURS_PER_YEAR
+        }
+    }
+
+    /// Streaming rainflow counter (ASTM E1049 three-point method). Samples are fed one at a
+    /// time; cycles are reported as (range, count) as soon as they close.
+    #[derive(Clone, Debug, Default)]
+    pub struct RainflowCounter {
+        reversals: Vec<f32>,
+        pending: Option<f32>,
+        rising: Option<bool>,
+    }
+
+    impl RainflowCounter {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Feeds the next sample and returns the cycles it closes.
+        pub fn push(&mut self, value: f32) -> Vec<(f32, f32)> {
+            let Some(prev) = self.pending else {
+                self.pending = Some(value);
+                return Vec::new();
+            };
+            if value == prev {
+                return Vec::new();
+            }
+            let rising = value > prev;
+            let mut closed = Vec::new();
+            if self.rising != Some(rising) {
+                // The previous sample turned out to be a peak or valley.
+                self.reversals.push(prev);
+                closed = self.collapse();
+            }
+            self.rising = Some(rising);
+            self.pending = Some(value);
+            closed
+        }
+
+        /// Half cycles that are still open, including the run up to the latest sample.
+        pub fn residual(&self) -> Vec<(f32, f32)> {
+            let mut points = self.reversals.clone();
+            points.extend(self.pending);
+            points
+                .windows(2)
+                .map(|w| ((w[1] - w[0]).abs(), 0.5))
+                .collect()
+        }
+
+        fn collapse(&mut self) -> Vec<(f32, f32)> {
+            let mut closed = Vec::new();
+            while self.reversals.len() >= 3 {
+                let n = self.reversals.len();
+                let x = (self.reversals[n - 1] - self.reversals[n - 2]).abs();
+                let y = (self.reversals[n - 2] - self.reversals[n - 3]).abs();
+                if x < y {
+                    break;
+                }
+                if n == 3 {
+                    // Y contains the starting point.
+                    closed.push((y, 0.5));
+                    self.reversals.remove(0);
+                } else {
+                    closed.push((y, 1.0));
+                    self.reversals.drain(n - 3..n - 1);
+                }
+            }
+            closed
+        }
+    }
+
+    /// Running state of health for one simulation.
+    #[derive(Clone, Debug)]
+    pub struct DegradationTracker {
+        model: DegradationModel,
+        nominal_capacity_wh: f32,
+        counter: RainflowCounter,
+        closed_damage: f32,
+        start: Option<DateTime<Utc>>,
+        soh: f32,
+    }
+
+    impl DegradationTracker {
+        pub fn new(model: DegradationModel, nominal_capacity_wh: f32) -> Self {
+            Self {
+                model,
+                nominal_capacity_wh,
+                counter: RainflowCounter::new(),
+                closed_damage: 0.0,
+                start: None,
+                soh: 100.0,
+            }
+        }
+
+        /// Records the stored energy at `date` and returns the state of health, in percent.
+        /// Open half cycles count toward the damage so far; the result never increases.
+        pub fn update(&mut self, date: DateTime<Utc>, storage_wh: f32) -> f32 {
+            let start = *self.start.get_or_insert(date);
+            for (range, count) in self.counter.push(storage_wh) {
+                self.closed_damage += self.damage(range, count);
+            }
+            let open_damage: f32 = self
+                .counter
+                .residual()
+                .into_iter()
+                .map(|(range, count)| self.damage(range, count))
+                .sum();
+            let hours = (date - start).num_minutes() as f32 / 60.0;
+            let soh = 100.0 - self.model.fade(self.closed_damage + open_damage, hours);
+            self.soh = self.soh.min(soh);
+            self.soh
+        }
+
+        fn damage(&self, range_wh: f32, count: f32) -> f32 {
+            let depth = range_wh / self.nominal_capacity_wh * 100.0;
+            self.model.cycle_damage(depth, count)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// Cycle counts by range for a load history, closed and residual together.
+        fn count_cycles(samples: &[f32]) -> Vec<(f32, f32)> {
+            let mut counter = RainflowCounter::new();
+            let mut cycles: Vec<(f32, f32)> = samples
+                .iter()
+                .flat_map(|&sample| counter.push(sample))
+                .collect();
+            cycles.extend(counter.residual());
+            let mut by_range: Vec<(f32, f32)> = Vec::new();
+            for (range, count) in cycles {
+                match by_range.iter_mut().find(|(r, _)| *r == range) {
+                    Some(entry) => entry.1 += count,
+                    None => by_range.push((range, count)),
+                }
+            }
+            by_range.sort_by(|a, b| a.0.total_cmp(&b.0));
+            by_range
+        }
+
+        #[test]
+        fn rainflow_matches_astm_e1049_example() {
+            // ASTM E1049-85 figure 6 history and its rainflow count.
+            let cycles = count_cycles(&[-2.0, 1.0, -3.0, 5.0, -1.0, 3.0, -4.0, 4.0, -2.0]);
+            assert_eq!(
+                cycles,
+                vec![(3.0, 0.5), (4.0, 1.5), (6.0, 0.5), (8.0, 1.0), (9.0, 0.5)]
+            );
+        }
+
+        #[test]
+        fn rainflow_ignores_repeated_and_monotonic_samples() {
+            let cycles = count_cycles(&[0.0, 1.0, 1.0, 2.0, 3.0]);
+            assert_eq!(cycles, vec![(3.0, 0.5)]);
+        }
+
+        #[test]
+        fn end_of_life_past_the_calendar_is_none() {
+            use crate::energy_components::batteries::BatteryStorage;
+            use chrono::TimeDelta;
+
+            let mut battery = BatteryStorage::new(1000.0, 1.0, 80.0, 48.0, 90.0)
+                .with_degradation(DegradationModel::lithium_iron_phosphate());
+            let start = DateTime::<Utc>::MAX_UTC - TimeDelta::try_hours(1000).unwrap();
+            battery.battery_state.state_of_health_ts =
+                Some(vec![(start, 100.0), (DateTime::<Utc>::MAX_UTC, 99.99)]);
+            assert_eq!(battery.end_of_life_date(), None);
+        }
+
+        #[test]
+        fn cycle_damage_interpolates_the_life_curve() {
+            let model = DegradationModel::lithium_iron_phosphate();
+            // Halfway between 20% (12000 cycles) and 50% (6000 cycles).
+            assert!((model.cycle_damage(35.0, 1.0) - 1.0 / 9000.0).abs() < 1e-9);
+            // Shallower than the curve: linear in depth from the 10% point.
+            assert!((model.cycle_damage(5.0, 1.0) - 0.5 / 20000.0).abs() < 1e-9);
+            assert!((model.cycle_damage(100.0, 3000.0) - 1.0).abs() < 1e-6);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn dc_coupled_discharge_shares_the_inverter_with_pv() {
+        let start = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let hours = [start, start + TimeDelta::try_hours(1).unwrap()];
+        // Charge 200 W from PV, then meet a 300 W load with 80 W of PV still generating.
+        let generator =
+            PowerComponent::new_ts(None, Some(vec![(hours[0], 200.0), (hours[1], 80.0)]));
+        let utility = PowerComponent::new_ts(Some(vec![(hours[0], 0.0), (hours[1], 300.0)]), None);
+        let battery = BatteryStorage::new(1000.0, 1.0, 80.0, 48.0, 100.0)
+            .with_dc_coupling()
+            .dispatch(generator, utility, &[], Some(250.0), &SelfConsumption);
+        let grid_import = battery.battery_state.grid_import_w_ts.unwrap();
+        // Only 170 W of the 250 W inverter is left for the battery.
+        assert!((grid_import[1].1 - 50.0).abs() < 1e-3);
+    }
 }
 
 // Constructor that calculates capacity
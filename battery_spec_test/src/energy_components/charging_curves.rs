@@ -0,0 +1,125 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::path::Path;
+
+/// Maximum DC power a vehicle accepts as a function of its state of charge. Power is
+/// interpolated linearly between points and held flat beyond the first and last point.
+#[derive(Clone, Debug)]
+pub struct ChargingCurve {
+    pub name: String,
+    /// (state of charge as a fraction, maximum power in watts), sorted by state of charge.
+    pub points: Vec<(f32, f32)>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CurveRecord {
+    vehicle: String,
+    soc: f32,
+    max_power_w: f32,
+}
+
+impl ChargingCurve {
+    pub fn new(name: &str, mut points: Vec<(f32, f32)>) -> Self {
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self {
+            name: name.to_string(),
+            points,
+        }
+    }
+
+    pub fn max_power_w(&self, soc: f32) -> f32 {
+        let Some(&(first_soc, first_power)) = self.points.first() else {
+            return f32::INFINITY;
+        };
+        let (last_soc, last_power) = self.points[self.points.len() - 1];
+        if soc <= first_soc {
+            return first_power;
+        }
+        if soc >= last_soc {
+            return last_power;
+        }
+        let idx = self.points.iter().position(|(s, _)| *s >= soc).unwrap();
+        let (s0, p0) = self.points[idx - 1];
+        let (s1, p1) = self.points[idx];
+        p0 + (p1 - p0) * (soc - s0) / (s1 - s0)
+    }
+
+    /// Reads curves from a CSV with `vehicle`, `soc` and `max_power_w` columns, one row per
+    /// point. Curves come back in the order their vehicle first appears.
+    pub fn from_csv<P: AsRef<Path>>(path: P) -> Result<Vec<ChargingCurve>> {
+        let mut reader = csv::Reader::from_path(path)?;
+        let mut curves: Vec<ChargingCurve> = Vec::new();
+        for record in reader.deserialize() {
+            let record: CurveRecord = record?;
+            match curves.iter_mut().find(|curve| curve.name == record.vehicle) {
+                Some(curve) => curve.points.push((record.soc, record.max_power_w)),
+                None => curves.push(ChargingCurve {
+                    name: record.vehicle,
+                    points: vec![(record.soc, record.max_power_w)],
+                }),
+            }
+        }
+        Ok(curves
+            .into_iter()
+            .map(|curve| ChargingCurve::new(&curve.name, curve.points))
+            .collect())
+    }
+
+    /// Generic 400 V pack peaking at 150 kW and tapering from about 50% state of charge.
+    pub fn generic_400v() -> Self {
+        Self::new(
+            "generic-400v",
+            vec![
+                (0.0, 50000.0),
+                (0.1, 150000.0),
+                (0.5, 150000.0),
+                (0.6, 110000.0),
+                (0.8, 60000.0),
+                (0.9, 30000.0),
+                (1.0, 10000.0),
+            ],
+        )
+    }
+
+    /// Generic 800 V pack peaking at 250 kW and tapering from about 40% state of charge.
+    pub fn generic_800v() -> Self {
+        Self::new(
+            "generic-800v",
+            vec![
+                (0.0, 150000.0),
+                (0.1, 250000.0),
+                (0.4, 250000.0),
+                (0.6, 180000.0),
+                (0.8, 90000.0),
+                (0.9, 40000.0),
+                (1.0, 10000.0),
+            ],
+        )
+    }
+
+    /// Older or compact vehicle limited to about 50 kW.
+    pub fn generic_50kw() -> Self {
+        Self::new(
+            "generic-50kw",
+            vec![
+                (0.0, 45000.0),
+                (0.5, 50000.0),
+                (0.8, 35000.0),
+                (0.9, 20000.0),
+                (1.0, 5000.0),
+            ],
+        )
+    }
+
+    pub fn presets() -> Vec<ChargingCurve> {
+        vec![
+            Self::generic_400v(),
+            Self::generic_800v(),
+            Self::generic_50kw(),
+        ]
+    }
+
+    pub fn preset(name: &str) -> Option<ChargingCurve> {
+        Self::presets().into_iter().find(|curve| curve.name == name)
+    }
+}
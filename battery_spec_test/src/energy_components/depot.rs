@@ -0,0 +1,126 @@
+use crate::energy_components::ev_sessions::{EvSession, Vehicle, VehicleProfile};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer};
+use std::path::Path;
+
+fn deserialize_datetime<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    let date_fmt = "%Y-%m-%d %H:%M:%S%:z";
+    DateTime::parse_from_str(&s, date_fmt)
+        .map(|date| date.with_timezone(&Utc))
+        .map_err(serde::de::Error::custom)
+}
+
+/// One stop of a fleet vehicle at the depot, as read from the schedule file.
+#[derive(Clone, Debug, Deserialize)]
+pub struct DepotTrip {
+    pub vehicle_id: String,
+    #[serde(deserialize_with = "deserialize_datetime")]
+    pub return_time: DateTime<Utc>,
+    #[serde(deserialize_with = "deserialize_datetime")]
+    pub departure_time: DateTime<Utc>,
+    /// Energy the vehicle must take on before it leaves, in watt hours.
+    pub required_energy_wh: f32,
+    /// Vehicle profile supplying the battery, acceptance and connectors; the first profile
+    /// when left empty.
+    #[serde(default)]
+    pub profile: Option<String>,
+}
+
+/// Known fleet itineraries, used in place of sampled arrivals.
+#[derive(Clone, Debug)]
+pub struct FleetSchedule {
+    pub trips: Vec<DepotTrip>,
+}
+
+/// How one trip's charging turned out.
+#[derive(Clone, Debug)]
+pub struct DepotOutcome {
+    pub vehicle_id: String,
+    pub return_time: DateTime<Utc>,
+    pub departure_time: DateTime<Utc>,
+    pub charger_index: Option<usize>,
+    pub charger_model: Option<String>,
+    pub required_energy_wh: f32,
+    pub energy_delivered_wh: f32,
+    /// Set when the vehicle left with less than its required energy.
+    pub undercharged: bool,
+}
+
+impl FleetSchedule {
+    /// Reads a CSV with `vehicle_id`, `return_time`, `departure_time`, `required_energy_wh`
+    /// and an optional `profile` column.
+    pub fn from_csv<P: AsRef<Path>>(path: P) -> Result<FleetSchedule> {
+        let mut reader = csv::Reader::from_path(path)?;
+        let mut trips = Vec::new();
+        for record in reader.deserialize() {
+            let trip: DepotTrip = record?;
+            if trip.departure_time <= trip.return_time {
+                bail!("{} departs before it returns", trip.vehicle_id);
+            }
+            trips.push(trip);
+        }
+        Ok(FleetSchedule { trips })
+    }
+
+    /// One vehicle per trip, in schedule order. Each arrives at its return time with just
+    /// enough charge missing below the profile's target to need its required energy, and
+    /// leaves at its departure time whether or not it has it.
+    pub fn vehicles(&self, profiles: &[VehicleProfile]) -> Result<Vec<Vehicle>> {
+        self.trips
+            .iter()
+            .enumerate()
+            .map(|(id, trip)| {
+                let profile = match &trip.profile {
+                    Some(name) => profiles
+                        .iter()
+                        .find(|profile| &profile.name == name)
+                        .ok_or_else(|| anyhow!("no vehicle profile named {}", name))?,
+                    None => profiles
+                        .first()
+                        .ok_or_else(|| anyhow!("fleet schedule needs a vehicle profile"))?,
+                };
+                let required_soc = trip.required_energy_wh / profile.battery_capacity_wh;
+                let target_soc = profile.target_soc.max(required_soc.min(1.0));
+                Ok(Vehicle {
+                    id,
+                    profile: profile.name.clone(),
+                    arrival: trip.return_time,
+                    battery_capacity_wh: profile.battery_capacity_wh,
+                    max_acceptance_w: profile.max_acceptance_w,
+                    charging_curve: profile.charging_curve.clone(),
+                    arrival_soc: (target_soc - required_soc).max(0.0),
+                    target_soc,
+                    dwell: trip.departure_time - trip.return_time,
+                    departure: Some(trip.departure_time),
+                    connectors: profile.connectors.clone(),
+                    v2g_capable: profile.v2g_capable,
+                })
+            })
+            .collect()
+    }
+
+    /// Matches each trip to its session from a run over `vehicles`.
+    pub fn outcomes(&self, sessions: &[EvSession]) -> Vec<DepotOutcome> {
+        self.trips
+            .iter()
+            .zip(sessions.iter())
+            .map(|(trip, session)| DepotOutcome {
+                vehicle_id: trip.vehicle_id.clone(),
+                return_time: trip.return_time,
+                departure_time: trip.departure_time,
+                charger_index: session.charger_index,
+                charger_model: session.charger_model.clone(),
+                required_energy_wh: trip.required_energy_wh,
+                energy_delivered_wh: session.energy_delivered_wh,
+                // Energy given back over V2G had to be charged again first.
+                undercharged: session.energy_delivered_wh - session.energy_discharged_wh
+                    < trip.required_energy_wh * 0.999,
+            })
+            .collect()
+    }
+}
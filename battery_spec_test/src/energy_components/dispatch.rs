@@ -0,0 +1,115 @@
+use chrono::{DateTime, Timelike, Utc};
+
+/// What a dispatch strategy sees at one time step. Powers are in watts and energies in watt
+/// hours; storage bounds already account for depth of discharge and state of health.
+#[derive(Clone, Debug)]
+pub struct DispatchContext {
+    pub date: DateTime<Utc>,
+    pub generation_w: f32,
+    pub demand_w: f32,
+    pub storage_wh: f32,
+    pub min_storage_wh: f32,
+    pub max_storage_wh: f32,
+    /// One-way discharge efficiency as a fraction.
+    pub discharge_efficiency: f32,
+}
+
+impl DispatchContext {
+    /// Generation left after serving demand; negative when demand exceeds generation.
+    pub fn net_generation_w(&self) -> f32 {
+        self.generation_w - self.demand_w
+    }
+}
+
+/// Decides how PV, battery and grid serve the charger load at each time step.
+///
+/// The strategy only picks a battery setpoint on the AC side: positive to charge, negative to
+/// discharge. The battery then applies its own power, capacity and efficiency limits and the
+/// grid balances whatever is left.
+pub trait DispatchStrategy {
+    fn name(&self) -> String;
+    fn battery_setpoint_w(&self, ctx: &DispatchContext) -> f32;
+}
+
+/// Stores PV surplus and discharges to cover any deficit.
+#[derive(Clone, Debug)]
+pub struct SelfConsumption;
+
+impl DispatchStrategy for SelfConsumption {
+    fn name(&self) -> String {
+        "self-consumption".to_string()
+    }
+
+    fn battery_setpoint_w(&self, ctx: &DispatchContext) -> f32 {
+        ctx.net_generation_w()
+    }
+}
+
+/// Keeps grid import under `import_cap_w`. The battery discharges only to shave load above the
+/// cap and recharges from PV surplus and from the grid headroom below the cap.
+#[derive(Clone, Debug)]
+pub struct PeakShaving {
+    pub import_cap_w: f32,
+}
+
+impl DispatchStrategy for PeakShaving {
+    fn name(&self) -> String {
+        format!("peak-shaving-{}w", self.import_cap_w)
+    }
+
+    fn battery_setpoint_w(&self, ctx: &DispatchContext) -> f32 {
+        self.import_cap_w - (ctx.demand_w - ctx.generation_w)
+    }
+}
+
+/// Charges from the grid at `grid_charge_power_w` during cheap hours and discharges to cover
+/// load during peak hours. In the remaining hours PV surplus is stored but the battery is held.
+/// Hours are the hour of day of the series timestamps.
+#[derive(Clone, Debug)]
+pub struct TimeOfUseArbitrage {
+    pub charge_hours: Vec<u32>,
+    pub discharge_hours: Vec<u32>,
+    pub grid_charge_power_w: f32,
+}
+
+impl DispatchStrategy for TimeOfUseArbitrage {
+    fn name(&self) -> String {
+        "time-of-use".to_string()
+    }
+
+    fn battery_setpoint_w(&self, ctx: &DispatchContext) -> f32 {
+        let hour = ctx.date.hour();
+        let net = ctx.net_generation_w();
+        if self.charge_hours.contains(&hour) {
+            net.max(self.grid_charge_power_w)
+        } else if self.discharge_hours.contains(&hour) {
+            net
+        } else {
+            net.max(0.0)
+        }
+    }
+}
+
+/// Self-consumption that never discharges into the reserve, kept as a percentage of the usable
+/// capacity above the depth of discharge floor.
+#[derive(Clone, Debug)]
+pub struct ReserveHolding {
+    pub reserve_percent: f32,
+}
+
+impl DispatchStrategy for ReserveHolding {
+    fn name(&self) -> String {
+        format!("reserve-{}pct", self.reserve_percent)
+    }
+
+    fn battery_setpoint_w(&self, ctx: &DispatchContext) -> f32 {
+        let net = ctx.net_generation_w();
+        if net >= 0.0 {
+            return net;
+        }
+        let reserve_wh = ctx.min_storage_wh
+            + (ctx.max_storage_wh - ctx.min_storage_wh) * self.reserve_percent / 100.0;
+        let available = (ctx.storage_wh - reserve_wh).max(0.0) * ctx.discharge_efficiency;
+        -(-net).min(available)
+    }
+}
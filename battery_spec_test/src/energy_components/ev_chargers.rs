@@ -1,7 +1,122 @@
+use crate::energy_components::ev_sessions::Vehicle;
 use crate::energy_components::general_fun::PowerComponent;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use chrono::{DateTime, TimeDelta, Utc};
+use rand::Rng;
+use rand_distr::{Distribution, Exp, Weibull};
 use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Fraction by which computed full-load figures may exceed the nameplate. Datasheets round
+/// kVA and FLA and quote them at nominal voltage and efficiency.
+pub const RATING_TOLERANCE: f32 = 0.05;
+
+/// Plug standards a charger offers or a vehicle accepts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Connector {
+    /// SAE J1772 AC (Level 2).
+    J1772,
+    Ccs1,
+    Ccs2,
+    Chademo,
+    Nacs,
+}
+
+/// Shape of the time between failures.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum FailureDistribution {
+    /// Constant failure rate.
+    Exponential,
+    /// Shape above 1 models wear-out, below 1 infant mortality.
+    Weibull { shape: f32 },
+}
+
+/// Failure and repair behaviour of one charger. Repair times are exponential with mean
+/// `mttr_hours`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Reliability {
+    pub mtbf_hours: f32,
+    pub mttr_hours: f32,
+    pub distribution: FailureDistribution,
+}
+
+impl Reliability {
+    pub fn new(mtbf_hours: f32, mttr_hours: f32, distribution: FailureDistribution) -> Self {
+        Self {
+            mtbf_hours,
+            mttr_hours,
+            distribution,
+        }
+    }
+
+    /// Long-run share of time the charger is up.
+    pub fn steady_state_availability(&self) -> f32 {
+        self.mtbf_hours / (self.mtbf_hours + self.mttr_hours)
+    }
+
+    fn hours_to_failure<R: Rng>(&self, rng: &mut R) -> f32 {
+        match self.distribution {
+            FailureDistribution::Exponential => {
+                Exp::new(1.0 / self.mtbf_hours).unwrap().sample(rng)
+            }
+            FailureDistribution::Weibull { shape } => {
+                // Scale chosen so the mean time to failure equals the MTBF.
+                let scale = self.mtbf_hours / gamma(1.0 + 1.0 / shape);
+                Weibull::new(scale, shape).unwrap().sample(rng)
+            }
+        }
+    }
+
+    /// Draws alternating up and down periods from `start` to `end` and returns the down
+    /// periods, the last one cut off at `end`.
+    pub fn sample_outages<R: Rng>(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        rng: &mut R,
+    ) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        let repair = Exp::new(1.0 / self.mttr_hours).unwrap();
+        let hours = |h: f32| TimeDelta::try_seconds((h * 3600.0) as i64).unwrap();
+        let mut outages = Vec::new();
+        let mut now = start;
+        loop {
+            now += hours(self.hours_to_failure(rng));
+            if now >= end {
+                break;
+            }
+            let repaired = (now + hours(repair.sample(rng))).min(end);
+            outages.push((now, repaired));
+            now = repaired;
+        }
+        outages
+    }
+}
+
+/// Lanczos approximation of the gamma function for positive arguments.
+fn gamma(x: f32) -> f32 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    let x = x as f64 - 1.0;
+    let t = x + 7.5;
+    let series = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (i, c)| {
+            sum + c / (x + i as f64 + 1.0)
+        });
+    ((2.0 * std::f64::consts::PI).sqrt() * t.powf(x + 0.5) * (-t).exp() * series) as f32
+}
 
 #[derive(Clone, Debug)] // Ensure Charger can be cloned/copied
 pub struct Charger {
@@ -16,6 +131,24 @@ pub struct Charger {
     pub rated_power_kva: f32,
     pub power_factor_at_full_load: f32,
     pub efficiency_at_nominal_power: f32,
+    /// 3 for three-phase service (line-to-line voltage), 1 for single-phase.
+    pub input_phases: u8,
+    /// Draw in watts while no vehicle is connected.
+    pub standby_power_w: f32,
+    /// Whether the charger can take power back from a vehicle.
+    pub bidirectional: bool,
+    /// DC power limit when discharging a vehicle, in watts.
+    pub max_discharge_power_w: f32,
+    /// Catalog model name; empty for chargers built directly.
+    pub model: String,
+    /// Installed cost of the charger.
+    pub unit_cost: f32,
+    /// Connectors on the dispenser. Empty means any vehicle can plug in.
+    pub connectors: Vec<Connector>,
+    /// Failure model; `None` keeps the charger up throughout.
+    pub reliability: Option<Reliability>,
+    /// Periods the charger was out of service in the last simulation.
+    pub outages: Vec<(DateTime<Utc>, DateTime<Utc>)>,
     pub power: PowerComponent,
 }
 
@@ -45,45 +178,419 @@ impl Charger {
             rated_power_kva,
             power_factor_at_full_load,
             efficiency_at_nominal_power,
-            power: PowerComponent {
-                input_power_w_ts: None,
-                output_power_w_ts: None,
-            },
+            input_phases: 3,
+            standby_power_w: 0.0,
+            bidirectional: false,
+            max_discharge_power_w: 0.0,
+            model: String::new(),
+            unit_cost: 0.0,
+            connectors: Vec::new(),
+            reliability: None,
+            outages: Vec::new(),
+            power: PowerComponent::new_ts(None, None),
         }
     }
 
-    // Example method to add input power with timestamp
-    pub fn add_input_power_ts(&mut self, date1: &str, date2: &str) -> Result<()> {
-        let date_fmt = "%Y-%m-%d %H:%M:%S%:z"; // Ensure this format matches your input data
-        let d1 = DateTime::parse_from_str(date1, date_fmt)?;
-        let d2 = DateTime::parse_from_str(date2, date_fmt)?;
-        let input_power_w = PowerComponent::power(
-            self.input_voltage_vac,
-            self.fla_a,
-            self.efficiency_at_nominal_power,
-        )
-        .unwrap(); // Consider handling errors instead of unwrapping.
-        let output_power_w = PowerComponent::power(
+    pub fn with_model(mut self, model: &str, connectors: Vec<Connector>) -> Self {
+        self.model = model.to_string();
+        self.connectors = connectors;
+        self
+    }
+
+    /// Sets the installed cost of the charger.
+    pub fn with_unit_cost(mut self, unit_cost: f32) -> Self {
+        self.unit_cost = unit_cost;
+        self
+    }
+
+    /// Whether the vehicle can plug in here. A side with no connectors listed matches anything.
+    pub fn is_compatible(&self, vehicle: &Vehicle) -> bool {
+        self.connectors.is_empty()
+            || vehicle.connectors.is_empty()
+            || self
+                .connectors
+                .iter()
+                .any(|connector| vehicle.connectors.contains(connector))
+    }
+
+    pub fn with_input_phases(mut self, input_phases: u8) -> Self {
+        self.input_phases = input_phases;
+        self
+    }
+
+    /// Makes the charger bidirectional with the given DC discharge limit.
+    pub fn with_v2g(mut self, max_discharge_power_w: f32) -> Self {
+        self.bidirectional = true;
+        self.max_discharge_power_w = max_discharge_power_w;
+        self
+    }
+
+    pub fn with_reliability(mut self, reliability: Reliability) -> Self {
+        self.reliability = Some(reliability);
+        self
+    }
+
+    /// Whether the charger is out of service at `date`.
+    pub fn is_down(&self, date: DateTime<Utc>) -> bool {
+        self.outages
+            .iter()
+            .any(|&(failed, repaired)| failed <= date && date < repaired)
+    }
+
+    /// Share of `start..end` the charger was in service.
+    pub fn uptime(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> f32 {
+        let down_seconds: i64 = self
+            .outages
+            .iter()
+            .map(|&(failed, repaired)| (repaired.min(end) - failed.max(start)).num_seconds().max(0))
+            .sum();
+        1.0 - down_seconds as f32 / (end - start).num_seconds() as f32
+    }
+
+    pub fn with_standby_power(mut self, standby_power_w: f32) -> Self {
+        self.standby_power_w = standby_power_w;
+        self
+    }
+
+    fn apparent_power_va_at_current(&self, amps: f32) -> f32 {
+        if self.input_phases == 1 {
+            self.input_voltage_vac * amps
+        } else {
+            PowerComponent::power(self.input_voltage_vac, amps, 1.0).unwrap()
+        }
+    }
+
+    /// AC real power drawn while delivering `maximum_power_w`, in watts.
+    pub fn max_input_power_w(&self) -> f32 {
+        self.maximum_power_w / self.efficiency_at_nominal_power
+    }
+
+    /// Reactive power in VAR accompanying a real input power, taking the full-load power factor
+    /// to hold at every load.
+    pub fn reactive_power_var(&self, real_power_w: f32) -> f32 {
+        real_power_w * self.power_factor_at_full_load.acos().tan()
+    }
+
+    /// Apparent power in VA for a real input power.
+    pub fn apparent_power_va(&self, real_power_w: f32) -> f32 {
+        real_power_w / self.power_factor_at_full_load
+    }
+
+    /// Line current in amps for a real input power.
+    pub fn input_current_a(&self, real_power_w: f32) -> f32 {
+        self.apparent_power_va(real_power_w) / self.apparent_power_va_at_current(1.0)
+    }
+
+    /// Checks the nameplate is self-consistent at full output: apparent power within the kVA
+    /// rating, line current within the full-load amps, the breaker sized at 125% of FLA for a
+    /// continuous load, and a 50 or 60 Hz input. The kVA and FLA checks allow
+    /// `RATING_TOLERANCE`; the breaker must carry the full 125%.
+    pub fn check_ratings(&self) -> Result<()> {
+        let mut problems = Vec::new();
+        if !matches!(self.input_frequency_hz, 50 | 60) {
+            problems.push(format!(
+                "{} Hz input is not a utility frequency",
+                self.input_frequency_hz
+            ));
+        }
+        let full_load_w = self.max_input_power_w();
+        let apparent_kva = self.apparent_power_va(full_load_w) / 1000.0;
+        if apparent_kva > self.rated_power_kva * (1.0 + RATING_TOLERANCE) {
+            problems.push(format!(
+                "{apparent_kva:.1} kVA at full output exceeds the {} kVA rating",
+                self.rated_power_kva
+            ));
+        }
+        let current_a = self.input_current_a(full_load_w);
+        if current_a > self.fla_a * (1.0 + RATING_TOLERANCE) {
+            problems.push(format!(
+                "{current_a:.1} A at full output exceeds the {} A FLA",
+                self.fla_a
+            ));
+        }
+        if (self.breaker_rating_a as f32) < self.fla_a * 1.25 {
+            problems.push(format!(
+                "{} A breaker is below 125% of the {} A FLA",
+                self.breaker_rating_a, self.fla_a
+            ));
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "charger {} ratings: {}",
+                self.model,
+                problems.join("; ")
+            ))
+        }
+    }
+
+    /// Hourly reactive power in VAR matching the input power series.
+    pub fn reactive_power_ts(&self) -> Option<Vec<(DateTime<Utc>, f32)>> {
+        self.power.input_power_w_ts.as_ref().map(|input| {
+            input
+                .iter()
+                .map(|&(date, real_w)| (date, self.reactive_power_var(real_w)))
+                .collect()
+        })
+    }
+
+    /// DC power delivered to a connected vehicle at `soc`, limited by both the charger rating
+    /// and what the vehicle's charging curve accepts.
+    pub fn session_power_w(&self, vehicle: &Vehicle, soc: f32) -> f32 {
+        self.maximum_power_w.min(vehicle.acceptance_w(soc))
+    }
+}
+
+/// One charger model in a catalog, with the nameplate ratings `Charger::new` takes.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ChargerSpec {
+    pub name: String,
+    pub maximum_power_w: f32,
+    pub output_voltage_min_vdc: f32,
+    pub output_voltage_max_vdc: f32,
+    pub max_output_current_a: f32,
+    pub input_voltage_vac: f32,
+    pub input_frequency_hz: u16,
+    pub fla_a: f32,
+    pub breaker_rating_a: u16,
+    pub rated_power_kva: f32,
+    pub power_factor_at_full_load: f32,
+    pub efficiency_at_nominal_power: f32,
+    #[serde(default = "three_phase")]
+    pub input_phases: u8,
+    #[serde(default)]
+    pub standby_power_w: f32,
+    /// Installed cost of one charger of this model.
+    #[serde(default)]
+    pub unit_cost: f32,
+    #[serde(default)]
+    pub connectors: Vec<Connector>,
+    #[serde(default)]
+    pub reliability: Option<Reliability>,
+    /// DC discharge limit for bidirectional models; absent for charge-only ones.
+    #[serde(default)]
+    pub v2g_discharge_power_w: Option<f32>,
+}
+
+fn three_phase() -> u8 {
+    3
+}
+
+impl ChargerSpec {
+    pub fn build(&self) -> Charger {
+        let charger = Charger::new(
+            self.maximum_power_w,
+            self.output_voltage_min_vdc,
             self.output_voltage_max_vdc,
             self.max_output_current_a,
+            self.input_voltage_vac,
+            self.input_frequency_hz,
+            self.fla_a,
+            self.breaker_rating_a,
+            self.rated_power_kva,
             self.power_factor_at_full_load,
+            self.efficiency_at_nominal_power,
         )
-        .unwrap();
-        //instantiate dates and energy inputs and outputs
-        let mut curr_time = d1;
-        let mut input_power_w_ts: Vec<(DateTime<Utc>, f32)> = Vec::new();
-        let mut output_power_w_ts: Vec<(DateTime<Utc>, f32)> = Vec::new();
+        .with_input_phases(self.input_phases)
+        .with_standby_power(self.standby_power_w)
+        .with_unit_cost(self.unit_cost)
+        .with_model(&self.name, self.connectors.clone());
+        let charger = match self.v2g_discharge_power_w {
+            Some(max_discharge_power_w) => charger.with_v2g(max_discharge_power_w),
+            None => charger,
+        };
+        match &self.reliability {
+            Some(reliability) => charger.with_reliability(reliability.clone()),
+            None => charger,
+        }
+    }
+}
 
-        loop {
-            if curr_time == d2 {
-                break;
+/// Number of chargers of each catalog model at a site, e.g. `[("dcfc-150kw", 6)]`.
+pub type FleetMix = Vec<(String, usize)>;
+
+/// Named charger models read from a JSON file holding a list of `ChargerSpec`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ChargerCatalog {
+    pub chargers: Vec<ChargerSpec>,
+}
+
+impl ChargerCatalog {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<ChargerCatalog> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    pub fn spec(&self, name: &str) -> Option<&ChargerSpec> {
+        self.chargers.iter().find(|spec| spec.name == name)
+    }
+
+    /// Builds the chargers for a fleet mix, grouped by model in the order given. Fails on an
+    /// unknown model or one whose ratings do not pass `Charger::check_ratings`.
+    pub fn build_fleet(&self, fleet: &[(String, usize)]) -> Result<Vec<Charger>> {
+        let mut chargers = Vec::new();
+        for (model, count) in fleet {
+            let spec = self
+                .spec(model)
+                .ok_or_else(|| anyhow!("charger model {model} not in catalog"))?;
+            let charger = spec.build();
+            charger.check_ratings()?;
+            chargers.extend(std::iter::repeat_n(charger, *count));
+        }
+        Ok(chargers)
+    }
+}
+
+/// Counts the chargers of each model, in the order models first appear.
+pub fn fleet_mix(chargers: &[Charger]) -> FleetMix {
+    let mut fleet: FleetMix = Vec::new();
+    for charger in chargers {
+        match fleet.iter_mut().find(|(model, _)| *model == charger.model) {
+            Some((_, count)) => *count += 1,
+            None => fleet.push((charger.model.clone(), 1)),
+        }
+    }
+    fleet
+}
+
+/// Short label for a fleet mix, such as `dcfc-150kw x6 + level2-19kw x4`.
+pub fn fleet_label(fleet: &[(String, usize)]) -> String {
+    fleet
+        .iter()
+        .map(|(model, count)| format!("{model} x{count}"))
+        .collect::<Vec<String>>()
+        .join(" + ")
+}
+
+/// Combined draw of a set of chargers, hour by hour.
+#[derive(Clone, Debug)]
+pub struct SitePower {
+    pub real_power_w_ts: Vec<(DateTime<Utc>, f32)>,
+    pub reactive_power_var_ts: Vec<(DateTime<Utc>, f32)>,
+    pub apparent_power_va_ts: Vec<(DateTime<Utc>, f32)>,
+}
+
+impl SitePower {
+    /// Sums real and reactive power over the chargers' input series; apparent power is taken
+    /// from the sums, so chargers with different power factors combine correctly. V2G export
+    /// is not netted against the draw.
+    pub fn from_chargers(chargers: &[Charger]) -> Self {
+        let mut real_power_w_ts: Vec<(DateTime<Utc>, f32)> = Vec::new();
+        let mut reactive_power_var_ts: Vec<(DateTime<Utc>, f32)> = Vec::new();
+        for charger in chargers {
+            let (Some(real), Some(reactive)) = (
+                charger.power.input_power_w_ts.as_ref(),
+                charger.reactive_power_ts(),
+            ) else {
+                continue;
+            };
+            if real_power_w_ts.is_empty() {
+                real_power_w_ts = real.iter().map(|&(date, _)| (date, 0.0)).collect();
+                reactive_power_var_ts = real_power_w_ts.clone();
+            }
+            for (total, (_, w)) in real_power_w_ts.iter_mut().zip(real.iter()) {
+                total.1 += w;
+            }
+            for (total, (_, var)) in reactive_power_var_ts.iter_mut().zip(reactive.iter()) {
+                total.1 += var;
             }
-            input_power_w_ts.push((curr_time.into(), input_power_w));
-            output_power_w_ts.push((curr_time.into(), output_power_w));
-            curr_time += TimeDelta::try_hours(1).unwrap();
         }
-        self.power.input_power_w_ts = Some(input_power_w_ts);
-        self.power.output_power_w_ts = Some(output_power_w_ts);
-        Ok(())
+        let apparent_power_va_ts = real_power_w_ts
+            .iter()
+            .zip(reactive_power_var_ts.iter())
+            .map(|(&(date, p), &(_, q))| (date, (p * p + q * q).sqrt()))
+            .collect();
+        SitePower {
+            real_power_w_ts,
+            reactive_power_var_ts,
+            apparent_power_va_ts,
+        }
+    }
+
+    /// Highest hourly apparent power in kVA, for sizing the transformer and service.
+    pub fn peak_apparent_kva(&self) -> f32 {
+        self.apparent_power_va_ts
+            .iter()
+            .map(|(_, va)| va / 1000.0)
+            .fold(0.0, f32::max)
+    }
+
+    /// Hours in which apparent power exceeds a transformer rating in kVA.
+    pub fn overload_hours(&self, rating_kva: f32) -> usize {
+        self.apparent_power_va_ts
+            .iter()
+            .filter(|(_, va)| va / 1000.0 > rating_kva)
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn bundled_catalog_passes_the_rating_checks() {
+        let catalog = ChargerCatalog::from_file("chargers.json").unwrap();
+        for spec in &catalog.chargers {
+            spec.build().check_ratings().unwrap();
+        }
+    }
+
+    #[test]
+    fn breaker_must_carry_the_full_continuous_load() {
+        let catalog = ChargerCatalog::from_file("chargers.json").unwrap();
+        let mut charger = catalog.spec("dcfc-50kw").unwrap().build();
+        charger.breaker_rating_a = 80;
+        assert!(charger.check_ratings().is_err());
+    }
+
+    #[test]
+    fn gamma_matches_known_values() {
+        assert!((gamma(5.0) - 24.0).abs() < 1e-4);
+        assert!((gamma(0.5) - std::f32::consts::PI.sqrt()).abs() < 1e-5);
+        // Γ(1 + 1/1.5), the Weibull mean factor for shape 1.5.
+        assert!((gamma(1.0 + 1.0 / 1.5) - 0.902_745_3).abs() < 1e-5);
+    }
+
+    #[test]
+    fn weibull_failures_average_the_mtbf() {
+        let reliability =
+            Reliability::new(2000.0, 48.0, FailureDistribution::Weibull { shape: 1.5 });
+        let mut rng = StdRng::seed_from_u64(7);
+        let draws = 20000;
+        let mean = (0..draws)
+            .map(|_| reliability.hours_to_failure(&mut rng) as f64)
+            .sum::<f64>()
+            / draws as f64;
+        assert!((mean / 2000.0 - 1.0).abs() < 0.02, "mean {mean}");
+    }
+
+    #[test]
+    fn seeded_outages_repeat_and_match_availability() {
+        let reliability = Reliability::new(500.0, 50.0, FailureDistribution::Exponential);
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2124, 1, 1, 0, 0, 0).unwrap();
+        let outages = reliability.sample_outages(start, end, &mut StdRng::seed_from_u64(42));
+        assert_eq!(
+            outages,
+            reliability.sample_outages(start, end, &mut StdRng::seed_from_u64(42))
+        );
+        assert!(outages
+            .windows(2)
+            .all(|pair| pair[0].0 < pair[0].1 && pair[0].1 <= pair[1].0));
+        assert!(outages
+            .iter()
+            .all(|&(failed, repaired)| start <= failed && repaired <= end));
+        let down_seconds: i64 = outages
+            .iter()
+            .map(|&(failed, repaired)| (repaired - failed).num_seconds())
+            .sum();
+        let uptime = 1.0 - down_seconds as f32 / (end - start).num_seconds() as f32;
+        // 500 / (500 + 50)
+        assert!((uptime - reliability.steady_state_availability()).abs() < 0.005);
     }
 }
@@ -0,0 +1,720 @@
+use crate::energy_components::charging_curves::ChargingCurve;
+use crate::energy_components::depot::FleetSchedule;
+use crate::energy_components::ev_chargers::{Charger, Connector};
+use crate::energy_components::load_management::{LoadManager, PowerRequest};
+use crate::energy_components::smart_charging::{FlexibleSession, SmartCharging};
+use crate::energy_components::v2g::{V2gControl, V2gResource};
+use anyhow::Result;
+use chrono::{DateTime, TimeDelta, Utc};
+use rand::prelude::*;
+use rand_distr::Exp;
+use std::collections::VecDeque;
+
+const STEP_MINUTES: i64 = 1;
+
+/// Shortfall in watt hours small enough to count a session as at its target. Absorbs rounding
+/// on the way from energy to input power and back.
+const TARGET_TOLERANCE_WH: f32 = 0.1;
+
+/// A kind of vehicle that visits the site. Each arrival draws a profile by `weight` and then
+/// its arrival state of charge and dwell time uniformly from the given ranges.
+#[derive(Clone, Debug)]
+pub struct VehicleProfile {
+    pub name: String,
+    pub weight: f32,
+    pub battery_capacity_wh: f32,
+    pub max_acceptance_w: f32,
+    /// Tapers acceptance with state of charge; without one `max_acceptance_w` holds throughout.
+    pub charging_curve: Option<ChargingCurve>,
+    /// Arrival state of charge range, as fractions of capacity.
+    pub arrival_soc: (f32, f32),
+    pub target_soc: f32,
+    /// Range of how long the driver stays plugged in, in minutes.
+    pub dwell_minutes: (u32, u32),
+    /// Plugs the vehicle accepts; empty fits any charger.
+    pub connectors: Vec<Connector>,
+    /// Whether the vehicle can discharge to the site through a bidirectional charger.
+    pub v2g_capable: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Vehicle {
+    pub id: usize,
+    pub profile: String,
+    pub arrival: DateTime<Utc>,
+    pub battery_capacity_wh: f32,
+    pub max_acceptance_w: f32,
+    pub charging_curve: Option<ChargingCurve>,
+    pub arrival_soc: f32,
+    pub target_soc: f32,
+    pub dwell: TimeDelta,
+    /// Fixed time the vehicle leaves, charged or not, even from the queue; without one it
+    /// stays `dwell` from plug-in.
+    pub departure: Option<DateTime<Utc>>,
+    pub connectors: Vec<Connector>,
+    pub v2g_capable: bool,
+}
+
+impl Vehicle {
+    /// DC power the vehicle accepts at `soc`.
+    pub fn acceptance_w(&self, soc: f32) -> f32 {
+        match &self.charging_curve {
+            Some(curve) => curve.max_power_w(soc).min(self.max_acceptance_w),
+            None => self.max_acceptance_w,
+        }
+    }
+
+    /// Lowest power the vehicle accepts anywhere between two states of charge.
+    pub fn min_acceptance_w(&self, from_soc: f32, to_soc: f32) -> f32 {
+        let curve_points = self.charging_curve.iter().flat_map(|curve| {
+            curve
+                .points
+                .iter()
+                .map(|(soc, _)| *soc)
+                .filter(|soc| (from_soc..=to_soc).contains(soc))
+        });
+        [from_soc, to_soc]
+            .into_iter()
+            .chain(curve_points)
+            .map(|soc| self.acceptance_w(soc))
+            .fold(f32::INFINITY, f32::min)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionStatus {
+    /// Still queued when the simulation ended.
+    Waiting,
+    /// Reached a charger.
+    Charged,
+    /// Found the queue full, or no charger it can plug into, on arrival and drove off.
+    Balked,
+    /// Ran out of patience, or reached its departure time, while queued.
+    Reneged,
+    /// Reached a charger that failed mid-session and left with what it had.
+    Interrupted,
+}
+
+/// Outcome of one vehicle's visit. `charger_index` and `plug_in` stay `None` for vehicles that
+/// never reached a charger.
+#[derive(Clone, Debug)]
+pub struct EvSession {
+    pub vehicle_id: usize,
+    pub profile: String,
+    pub status: SessionStatus,
+    pub charger_index: Option<usize>,
+    pub charger_model: Option<String>,
+    pub arrival: DateTime<Utc>,
+    pub plug_in: Option<DateTime<Utc>>,
+    pub departure: Option<DateTime<Utc>>,
+    pub wait_minutes: f32,
+    /// Energy needed to reach the target state of charge on arrival.
+    pub energy_requested_wh: f32,
+    pub energy_delivered_wh: f32,
+    /// Energy the vehicle gave back to the site, at the vehicle side, in watt hours.
+    pub energy_discharged_wh: f32,
+    /// Set when the vehicle was cut off by a failure, or balked or reneged while a charger
+    /// it could plug into was down.
+    pub outage_affected: bool,
+    pub arrival_soc: f32,
+    pub departure_soc: f32,
+    pub target_soc: f32,
+}
+
+/// Draws a vehicle for each arrival time from the weighted profiles.
+pub fn sample_vehicles(arrivals: &[DateTime<Utc>], profiles: &[VehicleProfile]) -> Vec<Vehicle> {
+    let mut rng = thread_rng();
+    arrivals
+        .iter()
+        .enumerate()
+        .map(|(id, &arrival)| {
+            let profile = profiles
+                .choose_weighted(&mut rng, |profile| profile.weight)
+                .expect("vehicle profiles need a positive total weight");
+            let (soc_low, soc_high) = profile.arrival_soc;
+            let (dwell_low, dwell_high) = profile.dwell_minutes;
+            Vehicle {
+                id,
+                profile: profile.name.clone(),
+                arrival,
+                battery_capacity_wh: profile.battery_capacity_wh,
+                max_acceptance_w: profile.max_acceptance_w,
+                charging_curve: profile.charging_curve.clone(),
+                arrival_soc: rng.gen_range(soc_low..=soc_high),
+                target_soc: profile.target_soc,
+                dwell: TimeDelta::try_minutes(rng.gen_range(dwell_low..=dwell_high).into())
+                    .unwrap(),
+                departure: None,
+                connectors: profile.connectors.clone(),
+                v2g_capable: profile.v2g_capable,
+            }
+        })
+        .collect()
+}
+
+/// How drivers behave when every charger is taken. Without limits every arrival waits as long
+/// as it takes.
+#[derive(Clone, Debug, Default)]
+pub struct QueuePolicy {
+    /// Arrivals that find this many vehicles already waiting drive off.
+    pub max_queue_length: Option<usize>,
+    /// Mean of the exponentially distributed time a driver waits before giving up.
+    pub mean_patience_minutes: Option<f32>,
+}
+
+/// Vehicle mix and driver behaviour for a site, held fixed across a sweep.
+#[derive(Clone, Debug)]
+pub struct StationConfig {
+    pub vehicle_profiles: Vec<VehicleProfile>,
+    pub queue_policy: QueuePolicy,
+    /// Site power sharing; `None` lets every charger run at its own limit.
+    pub load_manager: Option<LoadManager>,
+    /// Discharge from connected vehicles; `None` keeps charging one-way.
+    pub v2g: Option<V2gControl>,
+    /// Managed charging of long-dwell sessions; `None` charges every session uncontrolled.
+    pub smart_charging: Option<SmartCharging>,
+    /// Known fleet itineraries to run instead of sampled arrivals; trips name their vehicle
+    /// profile from `vehicle_profiles`.
+    pub fleet_schedule: Option<FleetSchedule>,
+}
+
+#[derive(Clone, Debug)]
+struct ActiveSession {
+    session: usize,
+    soc: f32,
+    unplug_at: DateTime<Utc>,
+}
+
+/// Event-level simulation of vehicles charging at a station. Time advances in one-minute
+/// steps: arrivals join a first-come first-served queue, take the fastest free charger they can
+/// plug into, and leave once they reach their target state of charge or their dwell time runs
+/// out. The queue policy decides who balks on arrival and who gives up waiting, and an optional
+/// load manager shares a site power limit between the sessions. With V2G control, capable
+/// vehicles on bidirectional chargers stay for their full dwell and may discharge down to their
+/// target; this V2G pass runs before and independently of the site battery dispatch. Smart
+/// charging spreads long-dwell sessions over their stay.
+#[derive(Clone, Debug)]
+pub struct SessionSimulator {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Seeds charger outages and driver patience, so runs of clones of one simulator see the
+    /// same failures and the same drivers.
+    pub seed: u64,
+    pub queue_policy: QueuePolicy,
+    pub load_manager: Option<LoadManager>,
+    pub v2g: Option<V2gControl>,
+    pub smart_charging: Option<SmartCharging>,
+}
+
+impl SessionSimulator {
+    pub fn new(date1: &str, date2: &str) -> Result<Self> {
+        let date_fmt = "%Y-%m-%d %H:%M:%S%:z"; // Ensure this format matches your input data
+        Ok(Self {
+            start: DateTime::parse_from_str(date1, date_fmt)?.into(),
+            end: DateTime::parse_from_str(date2, date_fmt)?.into(),
+            seed: thread_rng().gen(),
+            queue_policy: QueuePolicy::default(),
+            load_manager: None,
+            v2g: None,
+            smart_charging: None,
+        })
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_queue_policy(mut self, queue_policy: QueuePolicy) -> Self {
+        self.queue_policy = queue_policy;
+        self
+    }
+
+    /// Caps the combined charger input power and shares it between sessions.
+    pub fn with_load_manager(mut self, load_manager: LoadManager) -> Self {
+        self.load_manager = Some(load_manager);
+        self
+    }
+
+    pub fn with_v2g(mut self, v2g: V2gControl) -> Self {
+        self.v2g = Some(v2g);
+        self
+    }
+
+    fn hours(&self) -> Vec<DateTime<Utc>> {
+        let mut hours = Vec::new();
+        let mut curr_time = self.start;
+        while curr_time < self.end {
+            hours.push(curr_time);
+            curr_time += TimeDelta::try_hours(1).unwrap();
+        }
+        hours
+    }
+
+    /// Schedules long-dwell sessions instead of charging them flat out on arrival.
+    pub fn with_smart_charging(mut self, smart_charging: SmartCharging) -> Self {
+        self.smart_charging = Some(smart_charging);
+        self
+    }
+
+    fn holds_for_v2g(&self, charger: &Charger, vehicle: &Vehicle) -> bool {
+        self.v2g.is_some() && charger.bidirectional && vehicle.v2g_capable
+    }
+
+    /// Runs the vehicles through the chargers and returns one session per vehicle. Each
+    /// charger's `power` is replaced with its hourly average AC input, DC output and V2G
+    /// export.
+    pub fn run(&self, chargers: &mut [Charger], vehicles: &[Vehicle]) -> Vec<EvSession> {
+        let step = TimeDelta::try_minutes(STEP_MINUTES).unwrap();
+        let step_hours = STEP_MINUTES as f32 / 60.0;
+        let hours = self.hours();
+        let mut input_wh = vec![vec![0.0_f32; hours.len()]; chargers.len()];
+        let mut output_wh = vec![vec![0.0_f32; hours.len()]; chargers.len()];
+        let mut loss_wh = vec![vec![0.0_f32; hours.len()]; chargers.len()];
+        let mut standby_wh = vec![vec![0.0_f32; hours.len()]; chargers.len()];
+        let mut export_wh = vec![vec![0.0_f32; hours.len()]; chargers.len()];
+
+        let mut order: Vec<usize> = (0..vehicles.len()).collect();
+        order.sort_by_key(|&idx| vehicles[idx].arrival);
+        let mut sessions: Vec<EvSession> = vehicles
+            .iter()
+            .map(|vehicle| EvSession {
+                vehicle_id: vehicle.id,
+                profile: vehicle.profile.clone(),
+                status: SessionStatus::Waiting,
+                charger_index: None,
+                charger_model: None,
+                arrival: vehicle.arrival,
+                plug_in: None,
+                departure: None,
+                wait_minutes: 0.0,
+                energy_requested_wh: (vehicle.target_soc - vehicle.arrival_soc).max(0.0)
+                    * vehicle.battery_capacity_wh,
+                energy_delivered_wh: 0.0,
+                energy_discharged_wh: 0.0,
+                outage_affected: false,
+                arrival_soc: vehicle.arrival_soc,
+                departure_soc: vehicle.arrival_soc,
+                target_soc: vehicle.target_soc,
+            })
+            .collect();
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        for charger in chargers.iter_mut() {
+            charger.outages = match &charger.reliability {
+                Some(reliability) => reliability.sample_outages(self.start, self.end, &mut rng),
+                None => Vec::new(),
+            };
+        }
+        // Drawn per vehicle up front so a driver's patience does not depend on who else queued.
+        let patience_minutes: Vec<Option<f32>> = match self.queue_policy.mean_patience_minutes {
+            Some(mean) => {
+                let dist = Exp::new(1.0 / mean).expect("mean patience must be positive");
+                vehicles
+                    .iter()
+                    .map(|_| Some(dist.sample(&mut rng)))
+                    .collect()
+            }
+            None => vec![None; vehicles.len()],
+        };
+        let mut next_arrival = 0;
+        // Queued vehicles with the time they give up, if they ever do.
+        let mut queue: VecDeque<(usize, Option<DateTime<Utc>>)> = VecDeque::new();
+        let mut active: Vec<Option<ActiveSession>> = vec![None; chargers.len()];
+
+        let mut now = self.start;
+        while now < self.end {
+            let hour = ((now - self.start).num_minutes() / 60) as usize;
+            let down: Vec<bool> = chargers
+                .iter()
+                .map(|charger| charger.is_down(now))
+                .collect();
+            // An outage only turns away vehicles that could have used the down charger.
+            let outage_blocks = |vehicle: &Vehicle| {
+                chargers
+                    .iter()
+                    .zip(down.iter())
+                    .any(|(charger, &is_down)| is_down && charger.is_compatible(vehicle))
+            };
+            while next_arrival < order.len() && vehicles[order[next_arrival]].arrival <= now {
+                let idx = order[next_arrival];
+                next_arrival += 1;
+                let vehicle = &vehicles[idx];
+                let arrival = vehicle.arrival;
+                let free_charger = (0..chargers.len()).any(|charger_idx| {
+                    active[charger_idx].is_none()
+                        && !down[charger_idx]
+                        && chargers[charger_idx].is_compatible(vehicle)
+                });
+                let queue_full = self
+                    .queue_policy
+                    .max_queue_length
+                    .is_some_and(|max_len| queue.len() >= max_len && !free_charger);
+                if queue_full
+                    || !chargers
+                        .iter()
+                        .any(|charger| charger.is_compatible(vehicle))
+                {
+                    sessions[idx].status = SessionStatus::Balked;
+                    sessions[idx].departure = Some(arrival);
+                    sessions[idx].outage_affected = outage_blocks(vehicle);
+                    continue;
+                }
+                let give_up_at = patience_minutes[idx].map(|minutes| {
+                    arrival + TimeDelta::try_seconds((minutes * 60.0) as i64).unwrap()
+                });
+                let give_up_at = match (give_up_at, vehicle.departure) {
+                    (Some(give_up_at), Some(departure)) => Some(give_up_at.min(departure)),
+                    (give_up_at, departure) => give_up_at.or(departure),
+                };
+                queue.push_back((idx, give_up_at));
+            }
+            queue.retain(|&(idx, give_up_at)| match give_up_at {
+                Some(deadline) if deadline <= now => {
+                    sessions[idx].status = SessionStatus::Reneged;
+                    sessions[idx].departure = Some(deadline);
+                    sessions[idx].outage_affected = outage_blocks(&vehicles[idx]);
+                    sessions[idx].wait_minutes =
+                        (deadline - vehicles[idx].arrival).num_seconds() as f32 / 60.0;
+                    false
+                }
+                _ => true,
+            });
+            for (charger_idx, slot) in active.iter_mut().enumerate() {
+                if let Some(current) = slot {
+                    let vehicle = &vehicles[current.session];
+                    if down[charger_idx] {
+                        sessions[current.session].status = SessionStatus::Interrupted;
+                        sessions[current.session].outage_affected = true;
+                        sessions[current.session].departure = Some(now);
+                        *slot = None;
+                    } else if (current.soc >= vehicle.target_soc
+                        && !self.holds_for_v2g(&chargers[charger_idx], vehicle))
+                        || now >= current.unplug_at
+                    {
+                        sessions[current.session].departure = Some(now);
+                        *slot = None;
+                    }
+                }
+            }
+            // In arrival order, each queued vehicle takes the most powerful free charger it can
+            // plug into; vehicles with no such charger keep their place in the queue.
+            let mut still_waiting = VecDeque::new();
+            while let Some((idx, give_up_at)) = queue.pop_front() {
+                let vehicle = &vehicles[idx];
+                let free_charger = (0..chargers.len())
+                    .rev()
+                    .filter(|&charger_idx| {
+                        active[charger_idx].is_none()
+                            && !down[charger_idx]
+                            && chargers[charger_idx].is_compatible(vehicle)
+                    })
+                    .max_by(|&a, &b| {
+                        chargers[a]
+                            .maximum_power_w
+                            .total_cmp(&chargers[b].maximum_power_w)
+                    });
+                let Some(charger_idx) = free_charger else {
+                    still_waiting.push_back((idx, give_up_at));
+                    continue;
+                };
+                sessions[idx].status = SessionStatus::Charged;
+                sessions[idx].charger_index = Some(charger_idx);
+                sessions[idx].charger_model = Some(chargers[charger_idx].model.clone());
+                sessions[idx].plug_in = Some(now);
+                sessions[idx].wait_minutes = (now - vehicle.arrival).num_seconds() as f32 / 60.0;
+                active[charger_idx] = Some(ActiveSession {
+                    session: idx,
+                    soc: vehicle.arrival_soc,
+                    unplug_at: vehicle.departure.unwrap_or(now + vehicle.dwell),
+                });
+            }
+            queue = still_waiting;
+            // Power each session could take this step, as AC input, before any site limit.
+            let (slots, mut requests): (Vec<usize>, Vec<PowerRequest>) = active
+                .iter()
+                .enumerate()
+                .filter_map(|(charger_idx, slot)| {
+                    let current = slot.as_ref()?;
+                    let vehicle = &vehicles[current.session];
+                    let charger = &chargers[charger_idx];
+                    let needed_wh =
+                        (vehicle.target_soc - current.soc).max(0.0) * vehicle.battery_capacity_wh;
+                    let output_w = charger
+                        .session_power_w(vehicle, current.soc)
+                        .min(needed_wh / step_hours);
+                    Some((
+                        charger_idx,
+                        PowerRequest {
+                            requested_w: output_w / charger.efficiency_at_nominal_power,
+                            plug_in: sessions[current.session].plug_in.unwrap(),
+                            soc: current.soc,
+                        },
+                    ))
+                })
+                .unzip();
+            if let Some(smart) = &self.smart_charging {
+                let managed: Vec<usize> = (0..slots.len())
+                    .filter(|&request_idx| {
+                        let current = active[slots[request_idx]].as_ref().unwrap();
+                        smart.manages(vehicles[current.session].dwell)
+                    })
+                    .collect();
+                let unmanaged_input_w: f32 = (0..slots.len())
+                    .filter(|request_idx| !managed.contains(request_idx))
+                    .map(|request_idx| requests[request_idx].requested_w)
+                    .sum();
+                let flexible: Vec<FlexibleSession> = managed
+                    .iter()
+                    .map(|&request_idx| {
+                        let charger = &chargers[slots[request_idx]];
+                        let current = active[slots[request_idx]].as_ref().unwrap();
+                        let vehicle = &vehicles[current.session];
+                        FlexibleSession {
+                            needed_wh: (vehicle.target_soc - current.soc).max(0.0)
+                                * vehicle.battery_capacity_wh,
+                            max_power_w: requests[request_idx].requested_w
+                                * charger.efficiency_at_nominal_power,
+                            guaranteed_power_w: charger
+                                .maximum_power_w
+                                .min(vehicle.min_acceptance_w(current.soc, vehicle.target_soc)),
+                            // Sessions still plugged in at the end of the run are closed
+                            // there, so plan them to finish by then.
+                            unplug_at: current.unplug_at.min(self.end),
+                            efficiency: charger.efficiency_at_nominal_power,
+                        }
+                    })
+                    .collect();
+                let scheduled_w = smart.schedule(now, &flexible, unmanaged_input_w, step_hours);
+                for (&request_idx, output_w) in managed.iter().zip(scheduled_w) {
+                    let charger = &chargers[slots[request_idx]];
+                    requests[request_idx].requested_w =
+                        output_w / charger.efficiency_at_nominal_power;
+                }
+            }
+            let granted_w: Vec<f32> = match &self.load_manager {
+                Some(manager) => manager.allocate(now, &requests),
+                None => requests.iter().map(|request| request.requested_w).collect(),
+            };
+            for (&charger_idx, &input_w) in slots.iter().zip(granted_w.iter()) {
+                let current = active[charger_idx].as_mut().unwrap();
+                let vehicle = &vehicles[current.session];
+                let charger = &chargers[charger_idx];
+                let needed_wh =
+                    (vehicle.target_soc - current.soc).max(0.0) * vehicle.battery_capacity_wh;
+                let delivered_wh =
+                    (input_w * charger.efficiency_at_nominal_power * step_hours).min(needed_wh);
+                current.soc = if needed_wh - delivered_wh <= TARGET_TOLERANCE_WH {
+                    vehicle.target_soc.max(current.soc)
+                } else {
+                    current.soc + delivered_wh / vehicle.battery_capacity_wh
+                };
+                sessions[current.session].energy_delivered_wh += delivered_wh;
+                sessions[current.session].departure_soc = current.soc;
+                let drawn_wh = delivered_wh / charger.efficiency_at_nominal_power;
+                output_wh[charger_idx][hour] += delivered_wh;
+                input_wh[charger_idx][hour] += drawn_wh;
+                loss_wh[charger_idx][hour] += drawn_wh - delivered_wh;
+            }
+            if let Some(v2g) = &self.v2g {
+                let (v2g_slots, resources): (Vec<usize>, Vec<V2gResource>) = active
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(charger_idx, slot)| {
+                        let current = slot.as_ref()?;
+                        let vehicle = &vehicles[current.session];
+                        let charger = &chargers[charger_idx];
+                        if !self.holds_for_v2g(charger, vehicle)
+                            || current.soc <= vehicle.target_soc
+                        {
+                            return None;
+                        }
+                        Some((
+                            charger_idx,
+                            V2gResource {
+                                surplus_wh: (current.soc - vehicle.target_soc)
+                                    * vehicle.battery_capacity_wh,
+                                capacity_wh: vehicle.battery_capacity_wh,
+                                target_wh: vehicle.target_soc * vehicle.battery_capacity_wh,
+                                max_discharge_w: charger.max_discharge_power_w,
+                                efficiency: charger.efficiency_at_nominal_power,
+                            },
+                        ))
+                    })
+                    .unzip();
+                let station_demand_w: f32 = granted_w.iter().sum();
+                let discharge_w = v2g.discharge_w(now, station_demand_w, &resources, step_hours);
+                for (&charger_idx, &dc_w) in v2g_slots.iter().zip(discharge_w.iter()) {
+                    let current = active[charger_idx].as_mut().unwrap();
+                    let vehicle = &vehicles[current.session];
+                    let charger = &chargers[charger_idx];
+                    let discharged_wh = dc_w * step_hours;
+                    let exported_wh = discharged_wh * charger.efficiency_at_nominal_power;
+                    current.soc -= discharged_wh / vehicle.battery_capacity_wh;
+                    sessions[current.session].energy_discharged_wh += discharged_wh;
+                    sessions[current.session].departure_soc = current.soc;
+                    // Only what reaches the AC side is exported; the conversion loss on
+                    // the way out comes from the vehicle, not the meter.
+                    export_wh[charger_idx][hour] += exported_wh;
+                }
+            }
+            for (charger_idx, slot) in active.iter().enumerate() {
+                if slot.is_none() && !down[charger_idx] {
+                    let idle_wh = chargers[charger_idx].standby_power_w * step_hours;
+                    input_wh[charger_idx][hour] += idle_wh;
+                    standby_wh[charger_idx][hour] += idle_wh;
+                }
+            }
+            now += step;
+        }
+        for slot in active.iter().flatten() {
+            sessions[slot.session].departure = Some(self.end);
+        }
+        for &(idx, _) in queue.iter() {
+            sessions[idx].wait_minutes =
+                (self.end - vehicles[idx].arrival).num_seconds() as f32 / 60.0;
+        }
+
+        let hourly = |wh: &[f32]| -> Option<Vec<(DateTime<Utc>, f32)>> {
+            Some(
+                hours
+                    .iter()
+                    .zip(wh.iter())
+                    .map(|(&date, &wh)| (date, wh))
+                    .collect(),
+            )
+        };
+        for (charger_idx, charger) in chargers.iter_mut().enumerate() {
+            charger.power.input_power_w_ts = hourly(&input_wh[charger_idx]);
+            charger.power.output_power_w_ts = hourly(&output_wh[charger_idx]);
+            charger.power.loss_w_ts = hourly(&loss_wh[charger_idx]);
+            charger.power.standby_w_ts = hourly(&standby_wh[charger_idx]);
+            charger.power.export_w_ts = hourly(&export_wh[charger_idx]);
+        }
+        sessions
+    }
+}
+
+/// Waiting and utilization figures for one simulation.
+#[derive(Clone, Debug)]
+pub struct QueueMetrics {
+    pub arrivals: usize,
+    pub charged: usize,
+    pub balked: usize,
+    pub reneged: usize,
+    /// Wait statistics over vehicles that reached a charger, interrupted or not.
+    pub mean_wait_minutes: f32,
+    pub p95_wait_minutes: f32,
+    /// Share of the simulated time each charger had a vehicle plugged in.
+    pub charger_utilization: Vec<f32>,
+}
+
+impl QueueMetrics {
+    pub fn from_sessions(
+        sessions: &[EvSession],
+        charger_count: usize,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Self {
+        let count = |status: SessionStatus| {
+            sessions
+                .iter()
+                .filter(|session| session.status == status)
+                .count()
+        };
+        let mut waits: Vec<f32> = sessions
+            .iter()
+            .filter(|session| {
+                matches!(
+                    session.status,
+                    SessionStatus::Charged | SessionStatus::Interrupted
+                )
+            })
+            .map(|session| session.wait_minutes)
+            .collect();
+        waits.sort_by(|a, b| a.total_cmp(b));
+        let mean_wait_minutes = if waits.is_empty() {
+            0.0
+        } else {
+            waits.iter().sum::<f32>() / waits.len() as f32
+        };
+        let p95_wait_minutes = if waits.is_empty() {
+            0.0
+        } else {
+            waits[((waits.len() as f32 * 0.95).ceil() as usize).max(1) - 1]
+        };
+        let total_minutes = (end - start).num_minutes() as f32;
+        let mut busy_minutes = vec![0.0_f32; charger_count];
+        for session in sessions {
+            if let (Some(idx), Some(plug_in), Some(departure)) =
+                (session.charger_index, session.plug_in, session.departure)
+            {
+                busy_minutes[idx] += (departure - plug_in).num_minutes() as f32;
+            }
+        }
+        QueueMetrics {
+            arrivals: sessions.len(),
+            charged: count(SessionStatus::Charged),
+            balked: count(SessionStatus::Balked),
+            reneged: count(SessionStatus::Reneged),
+            mean_wait_minutes,
+            p95_wait_minutes,
+            charger_utilization: busy_minutes
+                .into_iter()
+                .map(|minutes| minutes / total_minutes)
+                .collect(),
+        }
+    }
+
+    pub fn mean_utilization(&self) -> f32 {
+        if self.charger_utilization.is_empty() {
+            return 0.0;
+        }
+        self.charger_utilization.iter().sum::<f32>() / self.charger_utilization.len() as f32
+    }
+}
+
+/// How charger failures affected a simulation.
+#[derive(Clone, Debug)]
+pub struct AvailabilityMetrics {
+    /// Share of the simulated time each charger was in service.
+    pub charger_uptime: Vec<f32>,
+    pub outages: usize,
+    pub interrupted_sessions: usize,
+    /// Energy vehicles wanted but did not get because of outages, in watt hours. Counts the
+    /// shortfall of interrupted sessions and the full request of vehicles that balked or
+    /// reneged while a charger they could plug into was down.
+    pub lost_demand_wh: f32,
+}
+
+impl AvailabilityMetrics {
+    pub fn from_run(
+        chargers: &[Charger],
+        sessions: &[EvSession],
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Self {
+        AvailabilityMetrics {
+            charger_uptime: chargers
+                .iter()
+                .map(|charger| charger.uptime(start, end))
+                .collect(),
+            outages: chargers.iter().map(|charger| charger.outages.len()).sum(),
+            interrupted_sessions: sessions
+                .iter()
+                .filter(|session| session.status == SessionStatus::Interrupted)
+                .count(),
+            lost_demand_wh: sessions
+                .iter()
+                .filter(|session| session.outage_affected)
+                .map(|session| (session.energy_requested_wh - session.energy_delivered_wh).max(0.0))
+                .sum(),
+        }
+    }
+
+    pub fn mean_uptime(&self) -> f32 {
+        if self.charger_uptime.is_empty() {
+            return 1.0;
+        }
+        self.charger_uptime.iter().sum::<f32>() / self.charger_uptime.len() as f32
+    }
+}
@@ -0,0 +1,112 @@
+use crate::energy_components::batteries::BatteryPowerComponent;
+use crate::tariffs::{MonthlyBill, Tariff};
+use chrono::{DateTime, Utc};
+
+#[derive(Clone, Debug)]
+pub struct GridPowerComponent {
+    pub grid_import_w_ts: Option<Vec<(DateTime<Utc>, f32)>>,
+    pub grid_export_w_ts: Option<Vec<(DateTime<Utc>, f32)>>,
+    pub unserved_load_w_ts: Option<Vec<(DateTime<Utc>, f32)>>,
+    pub curtailed_w_ts: Option<Vec<(DateTime<Utc>, f32)>>,
+}
+
+/// The site's utility service. Imports are capped by both the service import capacity and the
+/// transformer rating, taken at unity power factor; exports are capped by the export capacity
+/// and anything beyond it is curtailed. An optional tariff prices the resulting flows.
+#[derive(Clone, Debug)]
+pub struct GridConnection {
+    pub import_capacity_w: f32,
+    pub export_capacity_w: f32,
+    pub transformer_rating_kva: f32,
+    pub tariff: Option<Tariff>,
+    pub grid_state: GridPowerComponent,
+}
+
+impl GridConnection {
+    pub fn new(
+        import_capacity_w: f32,
+        export_capacity_w: f32,
+        transformer_rating_kva: f32,
+    ) -> Self {
+        Self {
+            import_capacity_w,
+            export_capacity_w,
+            transformer_rating_kva,
+            tariff: None,
+            grid_state: GridPowerComponent {
+                grid_import_w_ts: None,
+                grid_export_w_ts: None,
+                unserved_load_w_ts: None,
+                curtailed_w_ts: None,
+            },
+        }
+    }
+
+    pub fn with_tariff(mut self, tariff: Tariff) -> Self {
+        self.tariff = Some(tariff);
+        self
+    }
+
+    /// Largest power the site can draw from the utility, in watts.
+    pub fn import_limit_w(&self) -> f32 {
+        self.import_capacity_w
+            .min(self.transformer_rating_kva * 1000.0)
+    }
+
+    /// Largest power the site can feed back to the utility, in watts.
+    pub fn export_limit_w(&self) -> f32 {
+        self.export_capacity_w
+            .min(self.transformer_rating_kva * 1000.0)
+    }
+
+    /// Serves the battery's residual import and surplus through the connection limits. Import
+    /// beyond the limit becomes unserved load and surplus beyond the export limit is curtailed.
+    pub fn update_power_component(mut self, battery_state: &BatteryPowerComponent) -> Self {
+        let import_limit = self.import_limit_w();
+        let export_limit = self.export_limit_w();
+        let mut grid_import: Vec<(DateTime<Utc>, f32)> = Vec::new();
+        let mut grid_export: Vec<(DateTime<Utc>, f32)> = Vec::new();
+        let mut unserved: Vec<(DateTime<Utc>, f32)> = Vec::new();
+        let mut curtailed: Vec<(DateTime<Utc>, f32)> = Vec::new();
+        for (&(date, needed), &(_, surplus)) in battery_state
+            .grid_import_w_ts
+            .clone()
+            .unwrap()
+            .iter()
+            .zip(battery_state.curtailment_w_ts.clone().unwrap().iter())
+        {
+            let imported = needed.min(import_limit);
+            let exported = surplus.min(export_limit);
+            grid_import.push((date, imported));
+            unserved.push((date, needed - imported));
+            grid_export.push((date, exported));
+            curtailed.push((date, surplus - exported));
+        }
+        self.grid_state.grid_import_w_ts = Some(grid_import);
+        self.grid_state.grid_export_w_ts = Some(grid_export);
+        self.grid_state.unserved_load_w_ts = Some(unserved);
+        self.grid_state.curtailed_w_ts = Some(curtailed);
+        self
+    }
+
+    /// Total load the grid could not serve, in watt hours.
+    pub fn energy_not_served_wh(&self) -> f32 {
+        self.grid_state
+            .unserved_load_w_ts
+            .as_ref()
+            .map_or(0.0, |unserved| unserved.iter().map(|(_, w)| w).sum())
+    }
+
+    /// Monthly bills for the simulated import and export under the tariff; empty without a
+    /// tariff or before `update_power_component` has run.
+    pub fn monthly_bills(&self) -> Vec<MonthlyBill> {
+        match (
+            &self.tariff,
+            &self.grid_state.grid_import_w_ts,
+            &self.grid_state.grid_export_w_ts,
+        ) {
+            (Some(tariff), Some(import), Some(export)) => tariff.monthly_bills(import, export),
+            _ => Vec::new(),
+        }
+    }
+}
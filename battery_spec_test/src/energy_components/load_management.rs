@@ -0,0 +1,201 @@
+use crate::energy_components::grid::GridConnection;
+use crate::energy_components::photovoltaic::pv_base_system::PvSystem;
+use chrono::{DateTime, Utc};
+
+/// Ceiling on the combined AC input power of all chargers.
+#[derive(Clone, Debug)]
+pub enum SiteLimit {
+    /// Fixed service limit in watts.
+    Static(f32),
+    /// PV output plus grid import limit, hour by hour.
+    /// Filled in by `LoadManager::with_available_power`.
+    AvailablePower,
+}
+
+/// How the site limit is split between plugged-in vehicles when their requests exceed it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AllocationPolicy {
+    /// Every session gets the same share; shares a session cannot use go to the others.
+    EqualShare,
+    /// Sessions that plugged in first are served in full first.
+    FirstCome,
+    /// Sessions with the lowest state of charge are served in full first.
+    SocPriority,
+}
+
+/// What one active session would draw from the site if unconstrained.
+#[derive(Clone, Debug)]
+pub struct PowerRequest {
+    /// AC input power in watts.
+    pub requested_w: f32,
+    pub plug_in: DateTime<Utc>,
+    pub soc: f32,
+}
+
+/// Site-level controller that caps aggregate charger power and shares it between sessions.
+#[derive(Clone, Debug)]
+pub struct LoadManager {
+    pub limit: SiteLimit,
+    pub policy: AllocationPolicy,
+    /// Hourly available power in watts for `SiteLimit::AvailablePower`.
+    pub available_w_ts: Option<Vec<(DateTime<Utc>, f32)>>,
+}
+
+impl LoadManager {
+    pub fn new(limit: SiteLimit, policy: AllocationPolicy) -> Self {
+        Self {
+            limit,
+            policy,
+            available_w_ts: None,
+        }
+    }
+
+    /// Builds the hourly available power from the site's PV output and the grid import limit.
+    /// The battery is left out: sessions are simulated before it is dispatched, so its state
+    /// of charge is not known yet.
+    pub fn with_available_power(mut self, pv: &PvSystem, grid: &GridConnection) -> Self {
+        let grid_w = grid.import_limit_w();
+        self.available_w_ts = pv
+            .clone()
+            .into_power_component()
+            .output_power_w_ts
+            .map(|ts| {
+                ts.into_iter()
+                    .map(|(date, pv_w)| (date, pv_w + grid_w))
+                    .collect()
+            });
+        self
+    }
+
+    /// Site limit in watts at `date`. Before the first hour the first hour's value holds, and
+    /// an empty available power series allows nothing.
+    pub fn limit_w(&self, date: DateTime<Utc>) -> f32 {
+        match self.limit {
+            SiteLimit::Static(limit_w) => limit_w,
+            SiteLimit::AvailablePower => {
+                let available = self
+                    .available_w_ts
+                    .as_ref()
+                    .expect("available power not set; call with_available_power");
+                let idx = available.partition_point(|(hour, _)| *hour <= date);
+                available
+                    .get(idx.saturating_sub(1))
+                    .map_or(0.0, |&(_, limit_w)| limit_w)
+            }
+        }
+    }
+
+    /// Power granted to each request at `date`, in the same order and units as the requests.
+    /// Requests are granted in full while their total stays under the limit.
+    pub fn allocate(&self, date: DateTime<Utc>, requests: &[PowerRequest]) -> Vec<f32> {
+        let limit_w = self.limit_w(date).max(0.0);
+        let total_w: f32 = requests.iter().map(|request| request.requested_w).sum();
+        if total_w <= limit_w {
+            return requests.iter().map(|request| request.requested_w).collect();
+        }
+        let mut order: Vec<usize> = (0..requests.len()).collect();
+        match self.policy {
+            AllocationPolicy::EqualShare => {
+                order.sort_by(|&a, &b| requests[a].requested_w.total_cmp(&requests[b].requested_w))
+            }
+            AllocationPolicy::FirstCome => order.sort_by_key(|&idx| requests[idx].plug_in),
+            AllocationPolicy::SocPriority => {
+                order.sort_by(|&a, &b| requests[a].soc.total_cmp(&requests[b].soc))
+            }
+        }
+        let mut granted = vec![0.0; requests.len()];
+        let mut remaining_w = limit_w;
+        for (served, &idx) in order.iter().enumerate() {
+            let share_w = match self.policy {
+                // Smallest requests first, so whatever they leave unused is split among the
+                // larger ones still waiting.
+                AllocationPolicy::EqualShare => remaining_w / (order.len() - served) as f32,
+                AllocationPolicy::FirstCome | AllocationPolicy::SocPriority => remaining_w,
+            };
+            granted[idx] = requests[idx].requested_w.min(share_w);
+            remaining_w -= granted[idx];
+        }
+        granted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn minute(minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, 8, minute, 0).unwrap()
+    }
+
+    /// `(requested W, plug-in minute, soc)` for each session.
+    fn requests(sessions: &[(f32, u32, f32)]) -> Vec<PowerRequest> {
+        sessions
+            .iter()
+            .map(|&(requested_w, plug_in, soc)| PowerRequest {
+                requested_w,
+                plug_in: minute(plug_in),
+                soc,
+            })
+            .collect()
+    }
+
+    const SESSIONS: [(f32, u32, f32); 3] = [(20.0, 10, 0.5), (50.0, 20, 0.2), (80.0, 0, 0.4)];
+
+    #[test]
+    fn grants_everything_under_the_limit() {
+        let manager = LoadManager::new(SiteLimit::Static(200.0), AllocationPolicy::EqualShare);
+        assert_eq!(
+            manager.allocate(minute(30), &requests(&SESSIONS)),
+            vec![20.0, 50.0, 80.0]
+        );
+    }
+
+    #[test]
+    fn equal_share_passes_unused_share_on() {
+        let manager = LoadManager::new(SiteLimit::Static(100.0), AllocationPolicy::EqualShare);
+        // 20 W takes less than its third; the other two split the remaining 80 W.
+        assert_eq!(
+            manager.allocate(minute(30), &requests(&SESSIONS)),
+            vec![20.0, 40.0, 40.0]
+        );
+    }
+
+    #[test]
+    fn first_come_serves_earliest_plug_in() {
+        let manager = LoadManager::new(SiteLimit::Static(100.0), AllocationPolicy::FirstCome);
+        assert_eq!(
+            manager.allocate(minute(30), &requests(&SESSIONS)),
+            vec![20.0, 0.0, 80.0]
+        );
+    }
+
+    #[test]
+    fn soc_priority_serves_emptiest_first() {
+        let manager = LoadManager::new(SiteLimit::Static(100.0), AllocationPolicy::SocPriority);
+        assert_eq!(
+            manager.allocate(minute(30), &requests(&SESSIONS)),
+            vec![0.0, 50.0, 50.0]
+        );
+    }
+
+    #[test]
+    fn available_power_follows_the_hour() {
+        let mut manager = LoadManager::new(SiteLimit::AvailablePower, AllocationPolicy::FirstCome);
+        let hour = |hour: u32| Utc.with_ymd_and_hms(2024, 1, 1, hour, 0, 0).unwrap();
+        manager.available_w_ts = Some(vec![(hour(8), 60.0), (hour(9), 150.0)]);
+        assert_eq!(manager.limit_w(minute(30)), 60.0);
+        assert_eq!(manager.limit_w(hour(9)), 150.0);
+        assert_eq!(
+            manager.allocate(minute(30), &requests(&SESSIONS)),
+            vec![0.0, 0.0, 60.0]
+        );
+    }
+
+    #[test]
+    fn empty_available_power_allows_nothing() {
+        let mut manager = LoadManager::new(SiteLimit::AvailablePower, AllocationPolicy::FirstCome);
+        manager.available_w_ts = Some(Vec::new());
+        assert_eq!(manager.limit_w(minute(30)), 0.0);
+    }
+}
@@ -1,6 +1,14 @@
 pub mod batteries;
+pub mod charging_curves;
+pub mod depot;
+pub mod dispatch;
 pub mod ev_chargers;
+pub mod ev_sessions;
+pub mod grid;
+pub mod load_management;
 pub mod photovoltaic;
+pub mod smart_charging;
+pub mod v2g;
 pub mod general_fun {
 
     use anyhow;
@@ -20,6 +28,13 @@ pub mod general_fun {
     pub struct PowerComponent {
         pub input_power_w_ts: Option<Vec<(DateTime<Utc>, f32)>>,
         pub output_power_w_ts: Option<Vec<(DateTime<Utc>, f32)>>,
+        /// Power lost converting input to output. Part of the input.
+        pub loss_w_ts: Option<Vec<(DateTime<Utc>, f32)>>,
+        /// Power drawn while idle. Part of the input, except for generators, which draw it
+        /// from the bus they feed.
+        pub standby_w_ts: Option<Vec<(DateTime<Utc>, f32)>>,
+        /// Power sent back to the bus, such as V2G discharge. Not part of the input.
+        pub export_w_ts: Option<Vec<(DateTime<Utc>, f32)>>,
     }
 
     impl PowerComponent {
@@ -27,6 +42,9 @@ pub mod general_fun {
             Self {
                 input_power_w_ts: None,
                 output_power_w_ts: None,
+                loss_w_ts: None,
+                standby_w_ts: None,
+                export_w_ts: None,
             }
         }
 
@@ -37,14 +55,65 @@ pub mod general_fun {
             Self {
                 input_power_w_ts,
                 output_power_w_ts,
+                loss_w_ts: None,
+                standby_w_ts: None,
+                export_w_ts: None,
             }
         }
 
+        /// Input less export: the draw seen at the bus, negative while the component exports
+        /// more than it draws.
+        pub fn net_input_w_ts(&self) -> Option<Vec<(DateTime<Utc>, f32)>> {
+            let input = self.input_power_w_ts.as_ref()?;
+            Some(match &self.export_w_ts {
+                Some(export) => input
+                    .iter()
+                    .zip(export.iter())
+                    .map(|(&(date, input_w), &(_, export_w))| (date, input_w - export_w))
+                    .collect(),
+                None => input.clone(),
+            })
+        }
+
+        /// Output less standby: what a generator adds to the bus, negative while its standby
+        /// draw is larger.
+        pub fn net_output_w_ts(&self) -> Option<Vec<(DateTime<Utc>, f32)>> {
+            let output = self.output_power_w_ts.as_ref()?;
+            Some(match &self.standby_w_ts {
+                Some(standby) => output
+                    .iter()
+                    .zip(standby.iter())
+                    .map(|(&(date, output_w), &(_, standby_w))| (date, output_w - standby_w))
+                    .collect(),
+                None => output.clone(),
+            })
+        }
+
         pub fn power(volts: f32, amps: f32, power_factor: f32) -> Result<f32, anyhow::Error> {
             let const_val: f32 = 3.0;
             Ok(const_val.sqrt() * volts * amps * power_factor)
         }
 
+        /// Adds one series across components, or `None` if any component lacks it.
+        fn sum_series(
+            components: &[PowerComponent],
+            series: impl Fn(&PowerComponent) -> &Option<Vec<(DateTime<Utc>, f32)>>,
+        ) -> Option<Vec<(DateTime<Utc>, f32)>> {
+            let mut total: Option<Vec<(DateTime<Utc>, f32)>> = None;
+            for component in components {
+                let values = series(component).as_ref()?;
+                match total.as_mut() {
+                    None => total = Some(values.clone()),
+                    Some(total) => {
+                        for (sum, (_, w)) in total.iter_mut().zip(values.iter()) {
+                            sum.1 += w;
+                        }
+                    }
+                }
+            }
+            total
+        }
+
         pub fn merge_power_components(
             components: Vec<PowerComponent>,
             time_cycle: f32,
@@ -105,6 +174,11 @@ pub mod general_fun {
                 demand: PowerComponent {
                     input_power_w_ts: Some(sums),
                     output_power_w_ts: None,
+                    loss_w_ts: Self::sum_series(&components, |component| &component.loss_w_ts),
+                    standby_w_ts: Self::sum_series(&components, |component| {
+                        &component.standby_w_ts
+                    }),
+                    export_w_ts: Self::sum_series(&components, |component| &component.export_w_ts),
                 },
                 time_cycles: time_cycle,
                 total_input,
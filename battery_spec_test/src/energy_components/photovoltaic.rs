@@ -1,5 +1,8 @@
 pub mod pv_base_system {
+    use super::inverter::Inverter;
+    use super::solar_geometry::ArrayGeometry;
     use crate::energy_components::general_fun::PowerComponent;
+    use anyhow::{bail, Result};
     use chrono::NaiveDateTime;
     use chrono::Utc;
     use chrono::{DateTime, FixedOffset};
@@ -8,6 +11,55 @@ pub mod pv_base_system {
     use serde::Deserializer;
     use std::path::PathBuf;
 
+    /// Irradiance columns every irradiance file carries after `index`.
+    const IRRADIANCE_COLUMNS: [&str; 9] = [
+        "kt", "fd", "G0", "D0", "B0", "B_inc", "D_inc", "R_inc", "G_inc",
+    ];
+
+    /// Optional weather columns the irradiance file may carry for cell temperature.
+    const WEATHER_COLUMNS: [&str; 2] = ["temp_air", "wind_speed"];
+
+    /// Wind speed assumed in m/s when the irradiance file has air temperature but no wind.
+    const DEFAULT_WIND_SPEED: f32 = 1.0;
+
+    /// Steady-state cell temperature from plane-of-array irradiance, air temperature and wind,
+    /// with the coefficient sets pvlib uses.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum CellTemperatureModel {
+        /// Heat loss `u0 + u1 * wind` in W/m²K.
+        Faiman { u0: f32, u1: f32 },
+        /// Sandia module temperature `G * exp(a + b * wind) + T_air`, with the cell
+        /// `delta_t` warmer at 1000 W/m².
+        Sapm { a: f32, b: f32, delta_t: f32 },
+    }
+
+    impl CellTemperatureModel {
+        pub fn faiman() -> Self {
+            CellTemperatureModel::Faiman { u0: 25.0, u1: 6.84 }
+        }
+
+        pub fn sapm_open_rack_glass_glass() -> Self {
+            CellTemperatureModel::Sapm {
+                a: -3.47,
+                b: -0.0594,
+                delta_t: 3.0,
+            }
+        }
+
+        /// Cell temperature in °C for irradiance in W/m², air temperature in °C and wind in m/s.
+        pub fn cell_temperature_c(&self, poa_w_m2: f32, temp_air_c: f32, wind_speed: f32) -> f32 {
+            let wind_speed = wind_speed.max(0.0);
+            match *self {
+                CellTemperatureModel::Faiman { u0, u1 } => {
+                    temp_air_c + poa_w_m2 / (u0 + u1 * wind_speed)
+                }
+                CellTemperatureModel::Sapm { a, b, delta_t } => {
+                    temp_air_c + poa_w_m2 * (a + b * wind_speed).exp() + poa_w_m2 / 1000.0 * delta_t
+                }
+            }
+        }
+    }
+
     #[derive(Clone, Debug, Deserialize)]
     struct Record {
         #[serde(rename = "date", deserialize_with = "deserialize_datetime")]
@@ -50,11 +102,27 @@ pub mod pv_base_system {
     #[derive(Clone, Debug)]
     pub struct PvSystem {
         pub num_panels: f32,
+        /// Rated DC output of one panel at 1000 W/m² (STC), in watts.
         pub panel_watts: f32,
         pub panel_cost: f32,
+        /// Fraction of the inverter's AC output that reaches the site bus.
         pub transformer_efficiency: f32,
+        /// Fraction of DC power the inverter delivers as AC when there is no `inverter`.
+        pub inverter_efficiency: f32,
+        /// Rated inverter with a load-dependent efficiency, clipping and night tare; `None`
+        /// converts at the flat `inverter_efficiency` without a limit.
+        pub inverter: Option<Inverter>,
+        /// Panel dimensions in metres.
         pub panel_width: f32,
         pub panel_length: f32,
+        /// Derates output by cell temperature when the irradiance file has `temp_air`;
+        /// `None`, or an hour without air temperature, keeps the cells at 25 °C.
+        pub temperature_model: Option<CellTemperatureModel>,
+        /// Fractional change in DC power per °C of cell temperature above 25 °C.
+        pub power_temp_coefficient: f32,
+        /// Site and orientation to transpose `G0`, `D0` and `B0` onto the panels; `None` uses
+        /// the file's precomputed `G_inc`.
+        pub geometry: Option<ArrayGeometry>,
         pub params: SolarParams,
     }
     impl PvSystem {
@@ -71,26 +139,22 @@ pub mod pv_base_system {
         ) -> Self {
             let _delta_time = 1.0;
             let mut reader = PathBuf::new();
-            let mut schema = Schema::with_capacity(9);
+            let mut schema = Schema::with_capacity(12);
             reader.push(params);
             schema.with_column(
                 "index".into(),
                 DataType::Datetime(datatypes::TimeUnit::Milliseconds, None),
             );
-            schema.with_column("kt".into(), DataType::Float32);
-            schema.with_column("fd".into(), DataType::Float32);
-            schema.with_column("G0".into(), DataType::Float32);
-            schema.with_column("D0".into(), DataType::Float32);
-            schema.with_column("B0".into(), DataType::Float32);
-            schema.with_column("B_inc".into(), DataType::Float32);
-            schema.with_column("D_inc".into(), DataType::Float32);
-            schema.with_column("R_inc".into(), DataType::Float32);
-            schema.with_column("G_inc".into(), DataType::Float32);
+            // Columns are matched by name, so the weather columns may sit anywhere or be
+            // missing.
+            for column in IRRADIANCE_COLUMNS.iter().chain(WEATHER_COLUMNS.iter()) {
+                schema.with_column((*column).into(), DataType::Float32);
+            }
 
             // Read the CSV file into a DataFrame
             let mut irradiance_records = CsvReadOptions::default()
                 .with_has_header(true)
-                .with_schema(Some(schema.into()))
+                .with_schema_overwrite(Some(schema.into()))
                 .try_into_reader_with_file_path(Some(reader))
                 .unwrap()
                 .finish()
@@ -103,44 +167,888 @@ pub mod pv_base_system {
                 panel_watts,
                 panel_cost: 0.0,
                 transformer_efficiency,
+                inverter_efficiency: 0.97,
+                inverter: None,
                 panel_width,
                 panel_length,
+                temperature_model: None,
+                power_temp_coefficient: -0.0035,
+                geometry: None,
                 params: SolarParams {
                     irradiance: irradiance_records.to_owned(),
                 },
             }
         }
 
-        pub fn into_power_component(self) -> PowerComponent {
+        /// Sets the installed cost of a single panel.
+        pub fn with_panel_cost(mut self, panel_cost: f32) -> Self {
+            self.panel_cost = panel_cost;
+            self
+        }
+
+        pub fn with_inverter_efficiency(mut self, inverter_efficiency: f32) -> Self {
+            self.inverter_efficiency = inverter_efficiency;
+            self
+        }
+
+        pub fn with_inverter(mut self, inverter: Inverter) -> Self {
+            self.inverter = Some(inverter);
+            self
+        }
+
+        /// Fits a default inverter rated at the array's DC rating over `dc_ac_ratio`.
+        pub fn with_dc_ac_ratio(self, dc_ac_ratio: f32) -> Self {
+            let ac_rating_w = self.dc_rating_w() / dc_ac_ratio;
+            self.with_inverter(Inverter::new(ac_rating_w))
+        }
+
+        /// Array DC rating in watts at STC.
+        pub fn dc_rating_w(&self) -> f32 {
+            self.num_panels * self.panel_watts
+        }
+
+        /// Array DC rating over the inverter AC rating, when there is an inverter.
+        pub fn dc_ac_ratio(&self) -> Option<f32> {
+            self.inverter
+                .as_ref()
+                .map(|inverter| self.dc_rating_w() / inverter.ac_rating_w)
+        }
+
+        /// Derates by cell temperature. Fails when the irradiance file has no `temp_air`
+        /// column to drive the model.
+        pub fn with_temperature_model(
+            mut self,
+            temperature_model: CellTemperatureModel,
+        ) -> Result<Self> {
+            if self.params.irradiance.column("temp_air").is_err() {
+                bail!("a cell temperature model needs a temp_air column in the irradiance file");
+            }
+            self.temperature_model = Some(temperature_model);
+            Ok(self)
+        }
+
+        pub fn with_power_temp_coefficient(mut self, power_temp_coefficient: f32) -> Self {
+            self.power_temp_coefficient = power_temp_coefficient;
+            self
+        }
+
+        pub fn with_geometry(mut self, geometry: ArrayGeometry) -> Self {
+            self.geometry = Some(geometry);
+            self
+        }
+
+        /// How the panels are held; fixed without an array geometry.
+        pub fn mounting_label(&self) -> &'static str {
+            self.geometry
+                .as_ref()
+                .map_or("fixed", |geometry| geometry.mounting.label())
+        }
+
+        /// Hourly plane-of-array irradiance in W/m², from the array geometry when there is one
+        /// and from `G_inc` otherwise.
+        pub fn poa_irradiance_w_m2(&self) -> Vec<f32> {
+            let irradiance = &self.params.irradiance;
+            let column = |name: &str| irradiance.column(name).unwrap().f32().unwrap().to_vec();
+            match &self.geometry {
+                Some(geometry) => {
+                    let dates = irradiance.column("date").unwrap().datetime().unwrap();
+                    dates
+                        .as_datetime_iter()
+                        .zip(column("G0"))
+                        .zip(column("D0").into_iter().zip(column("B0")))
+                        .map(|((date, global), (diffuse, beam))| {
+                            geometry.poa_irradiance_w_m2(
+                                date.unwrap(),
+                                global.unwrap_or(0.0),
+                                diffuse.unwrap_or(0.0),
+                                beam.unwrap_or(0.0),
+                            )
+                        })
+                        .collect()
+                }
+                // G_inc is stored in kW/m², unlike its W/m² components.
+                None => column("G_inc")
+                    .into_iter()
+                    .map(|g_inc| g_inc.unwrap() * 1000.0)
+                    .collect(),
+            }
+        }
+
+        /// DC output of the array for a plane-of-array irradiance in W/m², scaling the STC
+        /// rating linearly with irradiance.
+        pub fn dc_power_w(&self, poa_irradiance_w_m2: f32) -> f32 {
+            self.num_panels * self.panel_watts * poa_irradiance_w_m2.max(0.0) / 1000.0
+        }
+
+        /// Share of the 25 °C output the array keeps at a cell temperature.
+        pub fn temperature_factor(&self, cell_temperature_c: f32) -> f32 {
+            (1.0 + self.power_temp_coefficient * (cell_temperature_c - 25.0)).max(0.0)
+        }
+
+        /// Hourly `(air temperature, wind speed)` from the irradiance file, or `None` when it
+        /// has no air temperature. Hours with a null air temperature are `None`.
+        fn weather(&self) -> Option<Vec<Option<(f32, f32)>>> {
+            let irradiance = &self.params.irradiance;
+            let temp_air = irradiance.column("temp_air").ok()?.f32().ok()?;
+            let wind_speed = irradiance
+                .column("wind_speed")
+                .ok()
+                .and_then(|column| column.f32().ok());
+            Some(
+                temp_air
+                    .into_iter()
+                    .enumerate()
+                    .map(|(idx, temp)| {
+                        let wind = wind_speed
+                            .and_then(|column| column.get(idx))
+                            .unwrap_or(DEFAULT_WIND_SPEED);
+                        temp.map(|temp| (temp, wind))
+                    })
+                    .collect(),
+            )
+        }
+
+        /// AC power delivered to the site for a given DC output, after the inverter and
+        /// transformer.
+        pub fn ac_power_w(&self, dc_power_w: f32) -> f32 {
+            let inverter_ac_w = match &self.inverter {
+                Some(inverter) => inverter.ac_power_w(dc_power_w),
+                None => dc_power_w * self.inverter_efficiency,
+            };
+            inverter_ac_w * self.transformer_efficiency
+        }
+
+        /// Power the site supplies to the inverter's night tare for a given DC output, in
+        /// watts. The tare is drawn back through the transformer, so its losses add to it.
+        pub fn standby_power_w(&self, dc_power_w: f32) -> f32 {
+            self.inverter
+                .as_ref()
+                .map_or(0.0, |inverter| inverter.tare_w(dc_power_w))
+                / self.transformer_efficiency
+        }
+
+        /// DC power in watts the inverter cannot take at a given DC output.
+        pub fn clipped_power_w(&self, dc_power_w: f32) -> f32 {
+            self.inverter
+                .as_ref()
+                .map_or(0.0, |inverter| inverter.clipped_power_w(dc_power_w))
+        }
+
+        /// Hourly DC output of the array in watts, after temperature derating.
+        pub fn dc_power_w_ts(&self) -> Vec<(DateTime<Utc>, f32)> {
             fn naive_to_utc(naive_datetime: NaiveDateTime) -> DateTime<Utc> {
                 DateTime::from_naive_utc_and_offset(naive_datetime, Utc)
             }
             //println!("{:?}", self.params.irradiance.get_column_names());
             let date_column = self.params.irradiance.column("date").unwrap();
             //println!("{:?}", date_column);
-            let mpp_column = self
-                .params
-                .irradiance
-                .column("G_inc")
-                .unwrap()
-                .f32()
-                .unwrap()
-                .to_vec();
-            let input_power_w_ts: Vec<(DateTime<Utc>, f32)> = Vec::new();
-            let output_power_w_ts: Vec<(DateTime<Utc>, f32)> = mpp_column
+            let poa_column = self.poa_irradiance_w_m2();
+            let weather = self.temperature_model.and_then(|_| self.weather());
+            poa_column
                 .iter()
                 .zip(date_column.datetime().unwrap().as_datetime_iter())
-                .map(|(x, y)| {
-                    (
-                        naive_to_utc(y.unwrap()),
-                        x.unwrap() * self.num_panels * self.panel_watts,
-                    )
+                .enumerate()
+                .map(|(idx, (&poa_w_m2, y))| {
+                    let hour_weather = weather.as_ref().and_then(|weather| weather[idx]);
+                    let factor = match (self.temperature_model, hour_weather) {
+                        (Some(model), Some((temp_air, wind_speed))) => self.temperature_factor(
+                            model.cell_temperature_c(poa_w_m2, temp_air, wind_speed),
+                        ),
+                        _ => 1.0,
+                    };
+                    (naive_to_utc(y.unwrap()), self.dc_power_w(poa_w_m2) * factor)
                 })
-                .collect::<Vec<(DateTime<Utc>, f32)>>();
+                .collect::<Vec<(DateTime<Utc>, f32)>>()
+        }
+
+        /// Hourly DC power in watts clipped at the inverter rating. A battery on the DC bus
+        /// could store it; otherwise it is lost.
+        pub fn clipped_power_w_ts(&self) -> Vec<(DateTime<Utc>, f32)> {
+            self.dc_power_w_ts()
+                .into_iter()
+                .map(|(date, dc_w)| (date, self.clipped_power_w(dc_w)))
+                .collect()
+        }
+
+        /// Hourly DC array output as input, AC delivered to the site as output, the conversion
+        /// losses between them and the night tare drawn from the site as standby, all in
+        /// watts. Clipped power is left out of the losses; see `clipped_power_w_ts`.
+        pub fn into_power_component(self) -> PowerComponent {
+            let input_power_w_ts = self.dc_power_w_ts();
+            let output_power_w_ts: Vec<(DateTime<Utc>, f32)> = input_power_w_ts
+                .iter()
+                .map(|&(date, dc_w)| (date, self.ac_power_w(dc_w)))
+                .collect();
+            let loss_w_ts: Vec<(DateTime<Utc>, f32)> = input_power_w_ts
+                .iter()
+                .zip(output_power_w_ts.iter())
+                .map(|(&(date, dc_w), &(_, ac_w))| (date, dc_w - self.clipped_power_w(dc_w) - ac_w))
+                .collect();
+            let standby_w_ts: Vec<(DateTime<Utc>, f32)> = input_power_w_ts
+                .iter()
+                .map(|&(date, dc_w)| (date, self.standby_power_w(dc_w)))
+                .collect();
             PowerComponent {
                 input_power_w_ts: Some(input_power_w_ts),
                 output_power_w_ts: Some(output_power_w_ts),
+                loss_w_ts: Some(loss_w_ts),
+                standby_w_ts: Some(standby_w_ts),
+                export_w_ts: None,
+            }
+        }
+    }
+}
+
+pub mod solar_geometry {
+    use chrono::{DateTime, Datelike, FixedOffset, NaiveDateTime, TimeDelta, Timelike, Utc};
+
+    /// Solar constant in W/m².
+    const SOLAR_CONSTANT: f32 = 1367.0;
+
+    /// Where the sun is at one instant.
+    #[derive(Clone, Copy, Debug)]
+    pub struct SolarPosition {
+        pub zenith_deg: f32,
+        /// Clockwise from north.
+        pub azimuth_deg: f32,
+        /// Extraterrestrial irradiance normal to the sun's rays, in W/m².
+        pub extraterrestrial_w_m2: f32,
+    }
+
+    impl SolarPosition {
+        /// Sun position from Spencer's declination and equation of time, good to a fraction
+        /// of a degree.
+        pub fn at(date: DateTime<Utc>, latitude_deg: f32, longitude_deg: f32) -> Self {
+            let minutes = date.hour() as f32 * 60.0 + date.minute() as f32;
+            let day_angle = 2.0 * std::f32::consts::PI / 365.0
+                * (date.ordinal0() as f32 + (minutes / 60.0 - 12.0) / 24.0);
+            let (sin1, cos1) = day_angle.sin_cos();
+            let (sin2, cos2) = (2.0 * day_angle).sin_cos();
+            let (sin3, cos3) = (3.0 * day_angle).sin_cos();
+            let equation_of_time_min = 229.18
+                * (0.000075 + 0.001868 * cos1
+                    - 0.032077 * sin1
+                    - 0.014615 * cos2
+                    - 0.040849 * sin2);
+            let declination = 0.006918 - 0.399912 * cos1 + 0.070257 * sin1 - 0.006758 * cos2
+                + 0.000907 * sin2
+                - 0.002697 * cos3
+                + 0.00148 * sin3;
+            let solar_time_min = minutes + equation_of_time_min + 4.0 * longitude_deg;
+            let hour_angle = (solar_time_min / 4.0 - 180.0).to_radians();
+            let latitude = latitude_deg.to_radians();
+            let cos_zenith = (latitude.sin() * declination.sin()
+                + latitude.cos() * declination.cos() * hour_angle.cos())
+            .clamp(-1.0, 1.0);
+            let azimuth = hour_angle
+                .sin()
+                .atan2(hour_angle.cos() * latitude.sin() - declination.tan() * latitude.cos());
+            SolarPosition {
+                zenith_deg: cos_zenith.acos().to_degrees(),
+                azimuth_deg: (azimuth.to_degrees() + 180.0).rem_euclid(360.0),
+                extraterrestrial_w_m2: SOLAR_CONSTANT
+                    * (1.00011
+                        + 0.034221 * cos1
+                        + 0.00128 * sin1
+                        + 0.000719 * cos2
+                        + 0.000077 * sin2),
+            }
+        }
+
+        /// Kasten and Young relative air mass; `None` below the horizon.
+        pub fn air_mass(&self) -> Option<f32> {
+            if self.zenith_deg >= 90.0 {
+                return None;
+            }
+            Some(
+                1.0 / (self.zenith_deg.to_radians().cos()
+                    + 0.50572 * (96.07995 - self.zenith_deg).powf(-1.6364)),
+            )
+        }
+    }
+
+    /// How sky diffuse light on the horizontal is carried onto a tilted plane.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum TranspositionModel {
+        /// Uniform sky.
+        Isotropic,
+        /// Circumsolar share in proportion to beam transmittance.
+        HayDavies,
+        /// Perez 1990 circumsolar and horizon brightening, all-sites coefficients.
+        Perez,
+    }
+
+    /// Perez 1990 `f11, f12, f13, f21, f22, f23` for each sky clearness bin.
+    const PEREZ_COEFFICIENTS: [[f32; 6]; 8] = [
+        [-0.0080, 0.5880, -0.0620, -0.0600, 0.0720, -0.0220],
+        [0.1300, 0.6830, -0.1510, -0.0190, 0.0660, -0.0290],
+        [0.3300, 0.4870, -0.2210, 0.0550, -0.0640, -0.0260],
+        [0.5680, 0.1870, -0.2950, 0.1090, -0.1520, -0.0140],
+        [0.8730, -0.3920, -0.3620, 0.2260, -0.4620, 0.0010],
+        [1.1320, -1.2370, -0.4120, 0.2880, -0.8230, 0.0560],
+        [1.0600, -1.6000, -0.3590, 0.2640, -1.1270, 0.1310],
+        [0.6780, -0.3270, -0.2500, 0.1560, -1.3770, 0.2510],
+    ];
+
+    /// Upper clearness bound of each Perez bin but the last.
+    const PEREZ_CLEARNESS_BINS: [f32; 7] = [1.065, 1.23, 1.5, 1.95, 2.8, 4.5, 6.2];
+
+    /// Below this the beam on the horizontal is too oblique to scale back to normal incidence.
+    const MIN_COS_ZENITH: f32 = 0.0436;
+
+    /// How the panels are held. Trackers turn the panels every time step and ignore the
+    /// geometry's tilt and azimuth.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Mounting {
+        Fixed,
+        /// Rotation about a horizontal axis towards the sun.
+        SingleAxis {
+            /// Direction of the axis, clockwise from north; 180 is a north-south axis.
+            axis_azimuth_deg: f32,
+            /// Largest rotation either side of flat.
+            max_rotation_deg: f32,
+            /// Turn back from the sun at low elevations so rows do not shade each other.
+            backtrack: bool,
+            /// Panel width over row spacing, used for backtracking.
+            ground_coverage_ratio: f32,
+        },
+        /// Panels kept normal to the sun.
+        DualAxis,
+    }
+
+    impl Mounting {
+        /// North-south single-axis tracker with backtracking at a ground coverage ratio of 0.35.
+        pub fn single_axis(max_rotation_deg: f32) -> Self {
+            Mounting::SingleAxis {
+                axis_azimuth_deg: 180.0,
+                max_rotation_deg,
+                backtrack: true,
+                ground_coverage_ratio: 0.35,
+            }
+        }
+
+        pub fn label(&self) -> &'static str {
+            match self {
+                Mounting::Fixed => "fixed",
+                Mounting::SingleAxis { .. } => "single-axis",
+                Mounting::DualAxis => "dual-axis",
+            }
+        }
+    }
+
+    /// What instant an irradiance timestamp stands for.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum TimestampConvention {
+        /// The value is measured at the stamped time, or averaged over an interval centred
+        /// on it.
+        Instant,
+        /// The value is averaged over the interval of `minutes` that starts at the stamped
+        /// time, so the sun is placed at its middle.
+        IntervalStart { minutes: i64 },
+    }
+
+    /// Site and orientation of an array.
+    #[derive(Clone, Debug)]
+    pub struct ArrayGeometry {
+        pub latitude_deg: f32,
+        pub longitude_deg: f32,
+        /// Offset of the irradiance file's clock from UTC; -5 h for a file in US Eastern
+        /// standard time.
+        pub utc_offset: FixedOffset,
+        pub timestamp_convention: TimestampConvention,
+        /// Panel tilt from horizontal.
+        pub tilt_deg: f32,
+        /// Direction the panels face, clockwise from north; 180 faces south.
+        pub azimuth_deg: f32,
+        /// Ground reflectance.
+        pub albedo: f32,
+        pub transposition: TranspositionModel,
+        pub mounting: Mounting,
+    }
+
+    impl ArrayGeometry {
+        pub fn new(
+            latitude_deg: f32,
+            longitude_deg: f32,
+            utc_offset: FixedOffset,
+            tilt_deg: f32,
+            azimuth_deg: f32,
+        ) -> Self {
+            Self {
+                latitude_deg,
+                longitude_deg,
+                utc_offset,
+                timestamp_convention: TimestampConvention::Instant,
+                tilt_deg,
+                azimuth_deg,
+                albedo: 0.2,
+                transposition: TranspositionModel::Perez,
+                mounting: Mounting::Fixed,
             }
         }
+
+        pub fn with_timestamp_convention(
+            mut self,
+            timestamp_convention: TimestampConvention,
+        ) -> Self {
+            self.timestamp_convention = timestamp_convention;
+            self
+        }
+
+        /// UTC instant to place the sun at for a timestamp on the irradiance file's clock.
+        pub fn solar_time(&self, timestamp: NaiveDateTime) -> DateTime<Utc> {
+            let utc = timestamp.and_utc()
+                - TimeDelta::try_seconds(self.utc_offset.local_minus_utc().into()).unwrap();
+            match self.timestamp_convention {
+                TimestampConvention::Instant => utc,
+                TimestampConvention::IntervalStart { minutes } => {
+                    utc + TimeDelta::try_seconds(minutes * 30).unwrap()
+                }
+            }
+        }
+
+        pub fn with_albedo(mut self, albedo: f32) -> Self {
+            self.albedo = albedo;
+            self
+        }
+
+        pub fn with_transposition(mut self, transposition: TranspositionModel) -> Self {
+            self.transposition = transposition;
+            self
+        }
+
+        pub fn with_mounting(mut self, mounting: Mounting) -> Self {
+            self.mounting = mounting;
+            self
+        }
+
+        /// Panel tilt and azimuth in degrees with the sun at `sun`.
+        pub fn surface_orientation(&self, sun: &SolarPosition) -> (f32, f32) {
+            match self.mounting {
+                Mounting::Fixed => (self.tilt_deg, self.azimuth_deg),
+                Mounting::DualAxis => (sun.zenith_deg.min(90.0), sun.azimuth_deg),
+                Mounting::SingleAxis {
+                    axis_azimuth_deg,
+                    max_rotation_deg,
+                    backtrack,
+                    ground_coverage_ratio,
+                } => {
+                    // Stowed flat overnight.
+                    if sun.zenith_deg >= 90.0 {
+                        return (0.0, axis_azimuth_deg);
+                    }
+                    // Sun as (east, north, up) and the horizontal direction the panels turn
+                    // towards at positive rotation, 90 degrees clockwise from the axis.
+                    let (zenith, azimuth) =
+                        (sun.zenith_deg.to_radians(), sun.azimuth_deg.to_radians());
+                    let sun_vector = [
+                        zenith.sin() * azimuth.sin(),
+                        zenith.sin() * azimuth.cos(),
+                        zenith.cos(),
+                    ];
+                    let axis = axis_azimuth_deg.to_radians();
+                    let across = [axis.cos(), -axis.sin()];
+                    let mut rotation = (sun_vector[0] * across[0] + sun_vector[1] * across[1])
+                        .atan2(sun_vector[2]);
+                    if backtrack && ground_coverage_ratio > 0.0 {
+                        // Lorenzo et al. (2011): rows just clear each other's shadow.
+                        let ratio = rotation.cos().abs() / ground_coverage_ratio;
+                        if ratio < 1.0 {
+                            rotation -= rotation.signum() * ratio.acos();
+                        }
+                    }
+                    let limit = max_rotation_deg.to_radians();
+                    let rotation = rotation.clamp(-limit, limit);
+                    let normal = [
+                        rotation.sin() * across[0],
+                        rotation.sin() * across[1],
+                        rotation.cos(),
+                    ];
+                    let surface_azimuth = if rotation == 0.0 {
+                        axis_azimuth_deg
+                    } else {
+                        normal[0].atan2(normal[1]).to_degrees().rem_euclid(360.0)
+                    };
+                    (rotation.abs().to_degrees(), surface_azimuth)
+                }
+            }
+        }
+
+        /// Cosine of the angle between the sun and the panel normal; negative when the sun is
+        /// behind the panels.
+        pub fn cos_incidence(&self, sun: &SolarPosition) -> f32 {
+            let (tilt_deg, azimuth_deg) = self.surface_orientation(sun);
+            let zenith = sun.zenith_deg.to_radians();
+            let tilt = tilt_deg.to_radians();
+            zenith.cos() * tilt.cos()
+                + zenith.sin() * tilt.sin() * (sun.azimuth_deg - azimuth_deg).to_radians().cos()
+        }
+
+        /// Plane-of-array irradiance in W/m² from global, diffuse and beam horizontal
+        /// irradiance stamped `timestamp` on the irradiance file's clock.
+        pub fn poa_irradiance_w_m2(
+            &self,
+            timestamp: NaiveDateTime,
+            global_w_m2: f32,
+            diffuse_w_m2: f32,
+            beam_w_m2: f32,
+        ) -> f32 {
+            let sun = SolarPosition::at(
+                self.solar_time(timestamp),
+                self.latitude_deg,
+                self.longitude_deg,
+            );
+            self.poa_irradiance_at_w_m2(&sun, global_w_m2, diffuse_w_m2, beam_w_m2)
+        }
+
+        /// Plane-of-array irradiance in W/m² with the sun at `sun`.
+        pub fn poa_irradiance_at_w_m2(
+            &self,
+            sun: &SolarPosition,
+            global_w_m2: f32,
+            diffuse_w_m2: f32,
+            beam_w_m2: f32,
+        ) -> f32 {
+            let cos_zenith = sun.zenith_deg.to_radians().cos();
+            if cos_zenith <= 0.0 || global_w_m2 <= 0.0 {
+                return 0.0;
+            }
+            let tilt = self.surface_orientation(sun).0.to_radians();
+            let cos_incidence = self.cos_incidence(sun).max(0.0);
+            // Beam at normal incidence.
+            let beam_normal = beam_w_m2.max(0.0) / cos_zenith.max(MIN_COS_ZENITH);
+            let beam = beam_normal * cos_incidence;
+            let diffuse = diffuse_w_m2.max(0.0);
+            let isotropic = diffuse * (1.0 + tilt.cos()) / 2.0;
+            let sky = match self.transposition {
+                TranspositionModel::Isotropic => isotropic,
+                TranspositionModel::HayDavies => {
+                    let anisotropy = (beam_normal / sun.extraterrestrial_w_m2).clamp(0.0, 1.0);
+                    let ratio = cos_incidence / cos_zenith.max(MIN_COS_ZENITH);
+                    (1.0 - anisotropy) * isotropic + anisotropy * diffuse * ratio
+                }
+                TranspositionModel::Perez => {
+                    if diffuse <= 0.0 {
+                        0.0
+                    } else {
+                        let zenith = sun.zenith_deg.to_radians();
+                        let kappa_z3 = 1.041 * zenith.powi(3);
+                        let clearness =
+                            ((diffuse + beam_normal) / diffuse + kappa_z3) / (1.0 + kappa_z3);
+                        let brightness =
+                            diffuse * sun.air_mass().unwrap_or(0.0) / sun.extraterrestrial_w_m2;
+                        let bin = PEREZ_CLEARNESS_BINS
+                            .iter()
+                            .take_while(|&&bound| clearness >= bound)
+                            .count();
+                        let [f11, f12, f13, f21, f22, f23] = PEREZ_COEFFICIENTS[bin];
+                        let f1 = (f11 + f12 * brightness + f13 * zenith).max(0.0);
+                        let f2 = f21 + f22 * brightness + f23 * zenith;
+                        let b = cos_zenith.max(85.0f32.to_radians().cos());
+                        (diffuse
+                            * ((1.0 - f1) * (1.0 + tilt.cos()) / 2.0
+                                + f1 * cos_incidence / b
+                                + f2 * tilt.sin()))
+                        .max(0.0)
+                    }
+                }
+            };
+            let ground = global_w_m2 * self.albedo * (1.0 - tilt.cos()) / 2.0;
+            beam + sky + ground
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use chrono::NaiveDate;
+
+        /// `(hour, G0, D0, B0)` in W/m² from full_irradiance_data.csv for 2024-06-21, a clear
+        /// day stamped on local standard time.
+        const CLEAR_DAY: [(u32, f32, f32, f32); 15] = [
+            (5, 30.33, 8.42, 21.91),
+            (6, 173.16, 43.31, 129.85),
+            (7, 343.9, 78.28, 265.62),
+            (8, 528.34, 110.95, 417.4),
+            (9, 706.52, 139.08, 567.44),
+            (10, 856.04, 160.76, 695.28),
+            (11, 956.41, 174.52, 781.89),
+            (12, 993.14, 179.41, 813.73),
+            (13, 960.8, 175.11, 785.69),
+            (14, 864.16, 161.9, 702.26),
+            (15, 717.22, 140.68, 576.53),
+            (16, 540.21, 112.91, 427.3),
+            (17, 355.53, 80.48, 275.05),
+            (18, 183.4, 45.58, 137.82),
+            (19, 38.49, 10.61, 27.88),
+        ];
+
+        fn peak_hour(values: impl Iterator<Item = (u32, f32)>) -> u32 {
+            values
+                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+                .unwrap()
+                .0
+        }
+
+        #[test]
+        fn poa_peaks_with_ghi_on_a_clear_day() {
+            let geometry = ArrayGeometry::new(
+                41.1,
+                -73.4,
+                FixedOffset::west_opt(5 * 3600).unwrap(),
+                30.0,
+                180.0,
+            );
+            let date = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+            let ghi_peak = peak_hour(CLEAR_DAY.iter().map(|&(hour, global, _, _)| (hour, global)));
+            let poa_peak = peak_hour(CLEAR_DAY.iter().map(|&(hour, global, diffuse, beam)| {
+                let timestamp = date.and_hms_opt(hour, 0, 0).unwrap();
+                (
+                    hour,
+                    geometry.poa_irradiance_w_m2(timestamp, global, diffuse, beam),
+                )
+            }));
+            assert_eq!(ghi_peak, 12);
+            assert_eq!(poa_peak, ghi_peak);
+        }
+
+        #[test]
+        fn interval_start_places_the_sun_mid_interval() {
+            let geometry = ArrayGeometry::new(
+                41.1,
+                -73.4,
+                FixedOffset::west_opt(5 * 3600).unwrap(),
+                30.0,
+                180.0,
+            )
+            .with_timestamp_convention(TimestampConvention::IntervalStart { minutes: 60 });
+            let timestamp = NaiveDate::from_ymd_opt(2024, 6, 21)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap();
+            let expected = NaiveDate::from_ymd_opt(2024, 6, 21)
+                .unwrap()
+                .and_hms_opt(17, 30, 0)
+                .unwrap()
+                .and_utc();
+            assert_eq!(geometry.solar_time(timestamp), expected);
+        }
+
+        #[test]
+        fn sun_position_matches_spa() {
+            // Reda and Andreas (2004) example: 2003-10-17 12:30:30 at UTC-7, Golden, Colorado.
+            // SPA gives a zenith of 50.11162 and an azimuth of 194.34024 degrees.
+            let date = NaiveDate::from_ymd_opt(2003, 10, 17)
+                .unwrap()
+                .and_hms_opt(19, 30, 30)
+                .unwrap()
+                .and_utc();
+            let sun = SolarPosition::at(date, 39.742476, -105.1786);
+            assert!((sun.zenith_deg - 50.11162).abs() < 0.5, "{sun:?}");
+            assert!((sun.azimuth_deg - 194.34024).abs() < 0.5, "{sun:?}");
+        }
+
+        /// `(zenith, azimuth, extraterrestrial, DNI, DHI, Perez POA, Hay-Davies POA)` for a
+        /// south-facing 30 degree plane with an albedo of 0.2. The POA values follow pvlib
+        /// `perez` (allsitescomposite1990, Kasten-Young air mass) and `haydavies`, plus beam and
+        /// `get_ground_diffuse`.
+        const TRANSPOSITION_CASES: [(f32, f32, f32, f32, f32, f32, f32); 2] = [
+            (30.0, 200.0, 1330.0, 800.0, 100.0, 910.8554, 904.149),
+            (60.0, 120.0, 1400.0, 150.0, 200.0, 306.0413, 295.5581),
+        ];
+
+        #[test]
+        fn transposition_matches_pvlib() {
+            let geometry = ArrayGeometry::new(
+                41.1,
+                -73.4,
+                FixedOffset::west_opt(5 * 3600).unwrap(),
+                30.0,
+                180.0,
+            );
+            let perez = geometry.clone();
+            let hay_davies = geometry.with_transposition(TranspositionModel::HayDavies);
+            for (zenith_deg, azimuth_deg, extraterrestrial_w_m2, dni, dhi, perez_poa, hay_poa) in
+                TRANSPOSITION_CASES
+            {
+                let sun = SolarPosition {
+                    zenith_deg,
+                    azimuth_deg,
+                    extraterrestrial_w_m2,
+                };
+                let beam = dni * zenith_deg.to_radians().cos();
+                let global = beam + dhi;
+                let poa = perez.poa_irradiance_at_w_m2(&sun, global, dhi, beam);
+                assert!(
+                    (poa - perez_poa).abs() < 0.05,
+                    "Perez {poa} at {zenith_deg}"
+                );
+                let poa = hay_davies.poa_irradiance_at_w_m2(&sun, global, dhi, beam);
+                assert!(
+                    (poa - hay_poa).abs() < 0.05,
+                    "Hay-Davies {poa} at {zenith_deg}"
+                );
+            }
+        }
+    }
+}
+
+pub mod inverter {
+    /// Efficiency at fractions of the AC rating for a typical string inverter, from its
+    /// weighted-efficiency test points.
+    const DEFAULT_EFFICIENCY_CURVE: [(f32, f32); 7] = [
+        (0.05, 0.92),
+        (0.1, 0.955),
+        (0.2, 0.97),
+        (0.3, 0.975),
+        (0.5, 0.977),
+        (0.75, 0.976),
+        (1.0, 0.973),
+    ];
+
+    /// Night tare as a fraction of the AC rating.
+    const DEFAULT_NIGHT_TARE: f32 = 0.0001;
+
+    /// A PV inverter that converts DC up to its AC rating. Anything the array makes beyond
+    /// that is clipped.
+    #[derive(Clone, Debug)]
+    pub struct Inverter {
+        /// Largest AC output in watts.
+        pub ac_rating_w: f32,
+        /// `(AC output / AC rating, efficiency)` points in increasing load, interpolated
+        /// linearly and held flat past either end.
+        pub efficiency_curve: Vec<(f32, f32)>,
+        /// Power drawn in watts while the array produces nothing.
+        pub night_tare_w: f32,
+    }
+
+    impl Inverter {
+        pub fn new(ac_rating_w: f32) -> Self {
+            Self {
+                ac_rating_w,
+                efficiency_curve: DEFAULT_EFFICIENCY_CURVE.to_vec(),
+                night_tare_w: ac_rating_w * DEFAULT_NIGHT_TARE,
+            }
+        }
+
+        pub fn with_efficiency_curve(mut self, efficiency_curve: Vec<(f32, f32)>) -> Self {
+            self.efficiency_curve = efficiency_curve;
+            self
+        }
+
+        pub fn with_night_tare(mut self, night_tare_w: f32) -> Self {
+            self.night_tare_w = night_tare_w;
+            self
+        }
+
+        /// The same inverter scaled to another AC rating, with the night tare scaled along.
+        pub fn with_ac_rating(mut self, ac_rating_w: f32) -> Self {
+            if self.ac_rating_w > 0.0 {
+                self.night_tare_w *= ac_rating_w / self.ac_rating_w;
+            }
+            self.ac_rating_w = ac_rating_w;
+            self
+        }
+
+        /// Efficiency at an AC output given as a fraction of the rating.
+        pub fn efficiency(&self, load: f32) -> f32 {
+            let curve = &self.efficiency_curve;
+            let (Some(first), Some(last)) = (curve.first(), curve.last()) else {
+                return 1.0;
+            };
+            if load <= first.0 {
+                return first.1;
+            }
+            if load >= last.0 {
+                return last.1;
+            }
+            let idx = curve.partition_point(|&(point, _)| point <= load);
+            let ((x0, y0), (x1, y1)) = (curve[idx - 1], curve[idx]);
+            y0 + (y1 - y0) * (load - x0) / (x1 - x0)
+        }
+
+        /// AC output for a DC input of `dc_w` before clipping. The efficiency is looked up at
+        /// the output, so the AC output solves `ac = dc * efficiency(ac / rating)`.
+        fn unclipped_ac_w(&self, dc_w: f32) -> f32 {
+            let mut ac_w = dc_w * self.efficiency(dc_w / self.ac_rating_w);
+            for _ in 0..3 {
+                ac_w = dc_w * self.efficiency(ac_w / self.ac_rating_w);
+            }
+            ac_w
+        }
+
+        /// Largest DC input in watts the inverter converts without clipping.
+        pub fn max_dc_input_w(&self) -> f32 {
+            self.ac_rating_w / self.efficiency(1.0)
+        }
+
+        /// AC output in watts for a DC input, capped at the rating.
+        pub fn ac_power_w(&self, dc_w: f32) -> f32 {
+            if dc_w <= 0.0 {
+                return 0.0;
+            }
+            self.unclipped_ac_w(dc_w.min(self.max_dc_input_w()))
+                .min(self.ac_rating_w)
+        }
+
+        /// AC power in watts the inverter draws for a DC input: the night tare while there is
+        /// none.
+        pub fn tare_w(&self, dc_w: f32) -> f32 {
+            if dc_w <= 0.0 {
+                self.night_tare_w
+            } else {
+                0.0
+            }
+        }
+
+        /// DC input in watts above what the inverter can convert at its rating.
+        pub fn clipped_power_w(&self, dc_w: f32) -> f32 {
+            (dc_w - self.max_dc_input_w()).max(0.0)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn efficiency_follows_the_curve() {
+            let inverter = Inverter::new(100_000.0);
+            for (load, efficiency) in DEFAULT_EFFICIENCY_CURVE {
+                assert_eq!(inverter.efficiency(load), efficiency);
+            }
+            assert!((inverter.efficiency(0.4) - 0.976).abs() < 1e-6);
+            assert_eq!(inverter.efficiency(0.01), 0.92);
+            assert_eq!(inverter.efficiency(1.2), 0.973);
+        }
+
+        #[test]
+        fn ac_output_at_curve_points() {
+            let inverter = Inverter::new(100_000.0);
+            for (load, efficiency) in DEFAULT_EFFICIENCY_CURVE {
+                let ac_w = load * inverter.ac_rating_w;
+                let output_w = inverter.ac_power_w(ac_w / efficiency);
+                assert!((output_w - ac_w).abs() < 1.0, "{output_w} at {load}");
+                assert_eq!(inverter.clipped_power_w(ac_w / efficiency), 0.0);
+            }
+        }
+
+        #[test]
+        fn clips_above_the_rating() {
+            let inverter = Inverter::new(100_000.0);
+            // 100 kW / 0.973
+            assert!((inverter.max_dc_input_w() - 102_774.92).abs() < 0.1);
+            assert_eq!(inverter.ac_power_w(130_000.0), 100_000.0);
+            assert!((inverter.clipped_power_w(130_000.0) - 27_225.08).abs() < 0.1);
+        }
+
+        #[test]
+        fn tare_only_at_night() {
+            let inverter = Inverter::new(100_000.0);
+            assert_eq!(inverter.tare_w(0.0), 10.0);
+            assert_eq!(inverter.tare_w(500.0), 0.0);
+            assert_eq!(inverter.ac_power_w(0.0), 0.0);
+            assert_eq!(inverter.with_ac_rating(50_000.0).tare_w(0.0), 5.0);
+        }
     }
 }
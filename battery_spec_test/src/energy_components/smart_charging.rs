@@ -0,0 +1,219 @@
+use crate::energy_components::ev_chargers::Charger;
+use crate::energy_components::ev_sessions::{EvSession, SessionStatus};
+use crate::energy_components::photovoltaic::pv_base_system::PvSystem;
+use crate::tariffs::Tariff;
+use chrono::{DateTime, TimeDelta, Utc};
+
+/// What managed charging optimizes for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SmartChargingObjective {
+    /// Charge from PV left over after unmanaged sessions, drawing from the grid only as needed
+    /// to make departure.
+    PvSelfConsumption,
+    /// Charge in the cheapest tariff hours left before departure.
+    GridCost,
+}
+
+/// A managed session as the scheduler sees it at one step. Powers are DC in watts.
+#[derive(Clone, Debug)]
+pub struct FlexibleSession {
+    pub needed_wh: f32,
+    /// What the charger and vehicle allow right now.
+    pub max_power_w: f32,
+    /// Lowest power the vehicle accepts between now and its target, used to keep the deadline
+    /// safe as the charging curve tapers.
+    pub guaranteed_power_w: f32,
+    pub unplug_at: DateTime<Utc>,
+    pub efficiency: f32,
+}
+
+/// Delays or modulates long-dwell sessions so they still reach their target by departure.
+/// Sessions shorter than `min_dwell_minutes` are left to charge uncontrolled.
+#[derive(Clone, Debug)]
+pub struct SmartCharging {
+    pub objective: SmartChargingObjective,
+    pub min_dwell_minutes: u32,
+    /// Hourly site generation in watts, for `PvSelfConsumption`.
+    pub generation_w_ts: Option<Vec<(DateTime<Utc>, f32)>>,
+    /// Prices the hours for `GridCost`.
+    pub tariff: Option<Tariff>,
+}
+
+impl SmartCharging {
+    pub fn new(objective: SmartChargingObjective, min_dwell_minutes: u32) -> Self {
+        Self {
+            objective,
+            min_dwell_minutes,
+            generation_w_ts: None,
+            tariff: None,
+        }
+    }
+
+    pub fn with_generation(mut self, pv: &PvSystem) -> Self {
+        self.generation_w_ts = pv.clone().into_power_component().output_power_w_ts;
+        self
+    }
+
+    pub fn with_tariff(mut self, tariff: Tariff) -> Self {
+        self.tariff = Some(tariff);
+        self
+    }
+
+    pub fn manages(&self, dwell: TimeDelta) -> bool {
+        dwell.num_minutes() >= self.min_dwell_minutes.into()
+    }
+
+    fn generation_w(&self, date: DateTime<Utc>) -> f32 {
+        self.generation_w_ts.as_ref().map_or(0.0, |generation| {
+            let idx = generation.partition_point(|(hour, _)| *hour <= date);
+            generation.get(idx.wrapping_sub(1)).map_or(0.0, |(_, w)| *w)
+        })
+    }
+
+    /// Whether `date` is priced no higher than any later hour before `until`.
+    fn is_cheapest_hour(&self, date: DateTime<Utc>, until: DateTime<Utc>) -> bool {
+        let Some(tariff) = &self.tariff else {
+            return true;
+        };
+        let rate = tariff.energy_rate_at(date);
+        let mut hour = date;
+        while hour < until {
+            if tariff.energy_rate_at(hour) < rate {
+                return false;
+            }
+            hour += TimeDelta::try_hours(1).unwrap();
+        }
+        true
+    }
+
+    /// DC power for each managed session this step. Every session gets at least what it needs
+    /// to still reach its target at its guaranteed power; the objective decides how much more.
+    /// `unmanaged_input_w` is the AC draw of the sessions left uncontrolled.
+    pub fn schedule(
+        &self,
+        date: DateTime<Utc>,
+        sessions: &[FlexibleSession],
+        unmanaged_input_w: f32,
+        step_hours: f32,
+    ) -> Vec<f32> {
+        let forced_w: Vec<f32> = sessions
+            .iter()
+            .map(|session| {
+                let hours_left = (session.unplug_at - date).num_seconds() as f32 / 3600.0;
+                let later_wh = session.guaranteed_power_w * (hours_left - step_hours).max(0.0);
+                ((session.needed_wh - later_wh).max(0.0) / step_hours).min(session.max_power_w)
+            })
+            .collect();
+        match self.objective {
+            SmartChargingObjective::GridCost => sessions
+                .iter()
+                .zip(forced_w.iter())
+                .map(|(session, forced)| {
+                    if self.is_cheapest_hour(date, session.unplug_at) {
+                        session.max_power_w
+                    } else {
+                        *forced
+                    }
+                })
+                .collect(),
+            SmartChargingObjective::PvSelfConsumption => {
+                let forced_input_w: f32 = sessions
+                    .iter()
+                    .zip(forced_w.iter())
+                    .map(|(session, forced)| forced / session.efficiency)
+                    .sum();
+                let mut surplus_w =
+                    (self.generation_w(date) - unmanaged_input_w - forced_input_w).max(0.0);
+                // Sessions closest to departure take the surplus first.
+                let mut order: Vec<usize> = (0..sessions.len()).collect();
+                order.sort_by_key(|&idx| sessions[idx].unplug_at);
+                let mut power_w = forced_w.clone();
+                for idx in order {
+                    let session = &sessions[idx];
+                    let extra_w = (session.max_power_w - power_w[idx])
+                        .max(0.0)
+                        .min(surplus_w * session.efficiency);
+                    power_w[idx] += extra_w;
+                    surplus_w -= extra_w / session.efficiency;
+                }
+                power_w
+            }
+        }
+    }
+}
+
+/// Side-by-side figures for a managed and an uncontrolled run of the same vehicles. Grid import
+/// here is the station draw PV does not cover, before any battery.
+#[derive(Clone, Debug)]
+pub struct ChargingComparison {
+    pub mode: String,
+    pub energy_delivered_wh: f32,
+    pub pv_self_consumed_wh: f32,
+    pub grid_import_wh: f32,
+    pub peak_draw_w: f32,
+    /// Import priced at the tariff energy rates; zero without a tariff.
+    pub energy_cost: f64,
+    /// Sessions that reached a charger but left below their target.
+    pub sessions_below_target: usize,
+}
+
+impl ChargingComparison {
+    pub fn from_run(
+        mode: &str,
+        chargers: &[Charger],
+        sessions: &[EvSession],
+        pv: &PvSystem,
+        tariff: Option<&Tariff>,
+    ) -> Self {
+        let generation = pv
+            .clone()
+            .into_power_component()
+            .output_power_w_ts
+            .unwrap_or_default();
+        let mut comparison = ChargingComparison {
+            mode: mode.to_string(),
+            energy_delivered_wh: sessions
+                .iter()
+                .map(|session| session.energy_delivered_wh)
+                .sum(),
+            pv_self_consumed_wh: 0.0,
+            grid_import_wh: 0.0,
+            peak_draw_w: 0.0,
+            energy_cost: 0.0,
+            sessions_below_target: sessions
+                .iter()
+                .filter(|session| {
+                    session.status == SessionStatus::Charged
+                        && session.departure_soc < session.target_soc - 1e-3
+                })
+                .count(),
+        };
+        let Some(hours) = chargers
+            .first()
+            .and_then(|charger| charger.power.input_power_w_ts.clone())
+        else {
+            return comparison;
+        };
+        // V2G exports offset the draw of other chargers on the same bus.
+        let net_input: Vec<Vec<(DateTime<Utc>, f32)>> = chargers
+            .iter()
+            .filter_map(|charger| charger.power.net_input_w_ts())
+            .collect();
+        for (hour, &(date, _)) in hours.iter().enumerate() {
+            let draw_w: f32 = net_input.iter().map(|input| input[hour].1).sum();
+            let idx = generation.partition_point(|(pv_date, _)| *pv_date < date);
+            let pv_w = generation
+                .get(idx)
+                .filter(|(pv_date, _)| *pv_date == date)
+                .map_or(0.0, |(_, w)| *w);
+            let import_w = (draw_w - pv_w).max(0.0);
+            comparison.pv_self_consumed_wh += draw_w.min(pv_w).max(0.0);
+            comparison.grid_import_wh += import_w;
+            comparison.peak_draw_w = comparison.peak_draw_w.max(draw_w);
+            if let Some(tariff) = tariff {
+                comparison.energy_cost += import_w as f64 / 1000.0 * tariff.energy_rate_at(date);
+            }
+        }
+        comparison
+    }
+}
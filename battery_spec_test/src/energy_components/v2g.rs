@@ -0,0 +1,116 @@
+use crate::energy_components::dispatch::{DispatchContext, DispatchStrategy};
+use crate::energy_components::photovoltaic::pv_base_system::PvSystem;
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::sync::Arc;
+
+/// Treats the plugged-in, V2G-capable vehicles as one distributed battery. At each step the
+/// strategy sees the station's draw and the vehicles' combined state of charge, with each
+/// vehicle's departure target as the floor; a negative setpoint is exported from the vehicles
+/// and a positive one is ignored, since vehicles already charge on their own schedule.
+///
+/// This is a separate pass that runs inside the session simulation, before the site battery
+/// is dispatched. The strategy sees PV generation and the station's draw only; it does not
+/// know what the site battery will do, and the battery later dispatches against the station
+/// draw net of the V2G export. Vehicles and battery may therefore both discharge into the same
+/// deficit or one may cover what the other leaves, with no coordination between the two.
+#[derive(Clone)]
+pub struct V2gControl {
+    pub strategy: Arc<dyn DispatchStrategy>,
+    /// Hourly site generation in watts given to the strategy; zero without it.
+    pub generation_w_ts: Option<Vec<(DateTime<Utc>, f32)>>,
+}
+
+impl fmt::Debug for V2gControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("V2gControl")
+            .field("strategy", &self.strategy.name())
+            .field(
+                "generation_w_ts",
+                &self.generation_w_ts.as_ref().map(Vec::len),
+            )
+            .finish()
+    }
+}
+
+/// One vehicle that can discharge this step.
+#[derive(Clone, Debug)]
+pub struct V2gResource {
+    /// Energy above the departure target, in watt hours.
+    pub surplus_wh: f32,
+    pub capacity_wh: f32,
+    pub target_wh: f32,
+    /// DC discharge limit of the charger, in watts.
+    pub max_discharge_w: f32,
+    pub efficiency: f32,
+}
+
+impl V2gControl {
+    pub fn new(strategy: Arc<dyn DispatchStrategy>) -> Self {
+        Self {
+            strategy,
+            generation_w_ts: None,
+        }
+    }
+
+    pub fn with_generation(mut self, pv: &PvSystem) -> Self {
+        self.generation_w_ts = pv.clone().into_power_component().output_power_w_ts;
+        self
+    }
+
+    fn generation_w(&self, date: DateTime<Utc>) -> f32 {
+        self.generation_w_ts.as_ref().map_or(0.0, |generation| {
+            let idx = generation.partition_point(|(hour, _)| *hour <= date);
+            generation.get(idx.wrapping_sub(1)).map_or(0.0, |(_, w)| *w)
+        })
+    }
+
+    /// DC power each resource discharges over a step of `step_hours`, given the station's
+    /// current AC draw. Export is shared in proportion to what each vehicle can give.
+    pub fn discharge_w(
+        &self,
+        date: DateTime<Utc>,
+        station_demand_w: f32,
+        resources: &[V2gResource],
+        step_hours: f32,
+    ) -> Vec<f32> {
+        // AC each vehicle could export this step.
+        let available_w: Vec<f32> = resources
+            .iter()
+            .map(|resource| {
+                resource
+                    .max_discharge_w
+                    .min(resource.surplus_wh / step_hours)
+                    * resource.efficiency
+            })
+            .collect();
+        let total_available_w: f32 = available_w.iter().sum();
+        if total_available_w <= 0.0 {
+            return vec![0.0; resources.len()];
+        }
+        let storage_wh: f32 = resources
+            .iter()
+            .map(|resource| resource.target_wh + resource.surplus_wh)
+            .sum();
+        let setpoint = self.strategy.battery_setpoint_w(&DispatchContext {
+            date,
+            generation_w: self.generation_w(date),
+            demand_w: station_demand_w,
+            storage_wh,
+            min_storage_wh: resources.iter().map(|resource| resource.target_wh).sum(),
+            max_storage_wh: resources.iter().map(|resource| resource.capacity_wh).sum(),
+            discharge_efficiency: available_w
+                .iter()
+                .zip(resources.iter())
+                .map(|(w, resource)| w * resource.efficiency)
+                .sum::<f32>()
+                / total_available_w,
+        });
+        let share = ((-setpoint).max(0.0) / total_available_w).min(1.0);
+        available_w
+            .iter()
+            .zip(resources.iter())
+            .map(|(w, resource)| w * share / resource.efficiency)
+            .collect()
+    }
+}
@@ -0,0 +1,175 @@
+use crate::energy_components::batteries::BatteryStorage;
+use crate::energy_components::ev_chargers::Charger;
+use crate::energy_components::photovoltaic::pv_base_system::PvSystem;
+
+const HOURS_PER_YEAR: f64 = 8760.0;
+
+/// Cost and revenue assumptions shared by every run of a sweep. Panel and charger capex come
+/// from `PvSystem::panel_cost` and `Charger::unit_cost`; the rest is priced here. Rates are in
+/// percent.
+#[derive(Clone, Debug)]
+pub struct FinancialParams {
+    pub battery_cost_per_kwh: f64,
+    /// Yearly operations and maintenance as a share of capex.
+    pub om_percent_of_capex: f64,
+    pub charging_price_per_kwh: f64,
+    pub discount_rate: f64,
+    pub project_life_years: u32,
+}
+
+/// Project economics for one simulated configuration. The simulated period is scaled to a
+/// year and repeated flat over the project life.
+#[derive(Clone, Debug)]
+pub struct FinancialResult {
+    pub capex: f64,
+    pub annual_revenue: f64,
+    pub annual_opex: f64,
+    pub annual_energy_kwh: f64,
+    pub npv: f64,
+    pub irr: Option<f64>,
+    pub payback_years: Option<f64>,
+    /// Levelized cost per kWh delivered to vehicles.
+    pub lcoe: f64,
+}
+
+impl FinancialParams {
+    /// Up-front cost of the panels, battery energy capacity and chargers.
+    pub fn capex(&self, pv: &PvSystem, battery: &BatteryStorage, chargers: &[Charger]) -> f64 {
+        (pv.num_panels * pv.panel_cost) as f64
+            + battery.full_capacity_wh() as f64 / 1000.0 * self.battery_cost_per_kwh
+            + chargers
+                .iter()
+                .map(|charger| charger.unit_cost as f64)
+                .sum::<f64>()
+    }
+
+    /// Evaluates a configuration from what it did over `simulated_hours`: the energy sold to
+    /// vehicles and the utility bill for the same period.
+    pub fn evaluate(
+        &self,
+        capex: f64,
+        simulated_hours: f64,
+        energy_sold_kwh: f64,
+        grid_bill: f64,
+    ) -> FinancialResult {
+        let annualize = HOURS_PER_YEAR / simulated_hours;
+        let annual_energy_kwh = energy_sold_kwh * annualize;
+        let annual_revenue = annual_energy_kwh * self.charging_price_per_kwh;
+        let annual_opex = capex * self.om_percent_of_capex / 100.0 + grid_bill * annualize;
+        let cash_flows: Vec<f64> = std::iter::once(-capex)
+            .chain((0..self.project_life_years).map(|_| annual_revenue - annual_opex))
+            .collect();
+        let rate = self.discount_rate / 100.0;
+        let discounted_energy: f64 = (1..=self.project_life_years)
+            .map(|year| annual_energy_kwh / (1.0 + rate).powi(year as i32))
+            .sum();
+        let discounted_cost: f64 = capex
+            + (1..=self.project_life_years)
+                .map(|year| annual_opex / (1.0 + rate).powi(year as i32))
+                .sum::<f64>();
+        FinancialResult {
+            capex,
+            annual_revenue,
+            annual_opex,
+            annual_energy_kwh,
+            npv: npv(rate, &cash_flows),
+            irr: irr(&cash_flows),
+            payback_years: payback_years(&cash_flows),
+            lcoe: discounted_cost / discounted_energy,
+        }
+    }
+}
+
+/// Net present value of yearly cash flows, the first at year zero.
+pub fn npv(rate: f64, cash_flows: &[f64]) -> f64 {
+    cash_flows
+        .iter()
+        .enumerate()
+        .map(|(year, flow)| flow / (1.0 + rate).powi(year as i32))
+        .sum()
+}
+
+/// Internal rate of return by bisection, or `None` when NPV does not change sign between
+/// -99% and 1000%.
+pub fn irr(cash_flows: &[f64]) -> Option<f64> {
+    let (mut low, mut high) = (-0.99, 10.0);
+    let (npv_low, npv_high) = (npv(low, cash_flows), npv(high, cash_flows));
+    if npv_low.signum() == npv_high.signum() {
+        return None;
+    }
+    for _ in 0..200 {
+        let mid = (low + high) / 2.0;
+        let npv_mid = npv(mid, cash_flows);
+        if npv_mid.abs() < 1e-6 {
+            return Some(mid);
+        }
+        if npv_mid.signum() == npv_low.signum() {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    Some((low + high) / 2.0)
+}
+
+/// Years until cumulative undiscounted cash flow turns positive, interpolated within the year,
+/// or `None` if it never does.
+pub fn payback_years(cash_flows: &[f64]) -> Option<f64> {
+    let mut cumulative = 0.0;
+    for (year, flow) in cash_flows.iter().enumerate() {
+        let previous = cumulative;
+        cumulative += flow;
+        if cumulative >= 0.0 && year > 0 {
+            return Some(year as f64 - 1.0 + -previous / flow);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn npv_matches_spreadsheet() {
+        // =NPV(10%, 300, 400, 500) - 1000
+        let value = npv(0.1, &[-1000.0, 300.0, 400.0, 500.0]);
+        assert!((value - -21.0368).abs() < 1e-4);
+    }
+
+    #[test]
+    fn irr_matches_spreadsheet() {
+        // =IRR({-100, 39, 59, 55, 20}) and =IRR({-1000, 300, 400, 500})
+        let rate = irr(&[-100.0, 39.0, 59.0, 55.0, 20.0]).unwrap();
+        assert!((rate - 0.2809).abs() < 1e-4);
+        let rate = irr(&[-1000.0, 300.0, 400.0, 500.0]).unwrap();
+        assert!((rate - 0.0890).abs() < 1e-4);
+        assert_eq!(irr(&[100.0, 50.0]), None);
+    }
+
+    #[test]
+    fn payback_interpolates_within_the_year() {
+        assert_eq!(payback_years(&[-1000.0, 300.0, 400.0, 500.0]), Some(2.6));
+        assert_eq!(payback_years(&[-1000.0, 300.0, 300.0]), None);
+    }
+
+    #[test]
+    fn evaluate_annualizes_half_a_year() {
+        let params = FinancialParams {
+            battery_cost_per_kwh: 0.0,
+            om_percent_of_capex: 2.0,
+            charging_price_per_kwh: 0.5,
+            discount_rate: 5.0,
+            project_life_years: 10,
+        };
+        let result = params.evaluate(10000.0, 4380.0, 1000.0, 100.0);
+        assert!((result.annual_energy_kwh - 2000.0).abs() < 1e-9);
+        assert!((result.annual_revenue - 1000.0).abs() < 1e-9);
+        // 2% of capex plus the bill doubled to a year.
+        assert!((result.annual_opex - 400.0).abs() < 1e-9);
+        assert!((result.npv - -5366.959).abs() < 1e-3);
+        // 600 a year never recovers 10000 over a 10 year life.
+        assert_eq!(result.payback_years, None);
+        assert!((result.lcoe - 0.84752).abs() < 1e-5);
+    }
+}
@@ -1,27 +1,174 @@
 use crate::energy_components::batteries::BatteryStorage;
-use crate::energy_components::ev_chargers::Charger;
+use crate::energy_components::depot::DepotOutcome;
+use crate::energy_components::dispatch::DispatchStrategy;
+use crate::energy_components::ev_chargers::{
+    fleet_label, fleet_mix, Charger, ChargerCatalog, FleetMix, SitePower,
+};
+use crate::energy_components::ev_sessions::{
+    sample_vehicles, AvailabilityMetrics, EvSession, QueueMetrics, SessionSimulator, StationConfig,
+    Vehicle,
+};
 use crate::energy_components::general_fun::PowerComponent;
+use crate::energy_components::grid::GridConnection;
 use crate::energy_components::photovoltaic::pv_base_system::PvSystem;
+use crate::energy_components::photovoltaic::solar_geometry::Mounting;
+use crate::energy_components::smart_charging::ChargingComparison;
 use crate::time_processes::*;
 use chrono::{DateTime, Local, Utc};
 use surrealdb::engine::remote::ws::Client;
 use surrealdb::engine::remote::ws::Ws;
 use surrealdb::Surreal;
 pub mod energy_components;
+pub mod finance;
+#[cfg(feature = "ocpp")]
+pub mod ocpp;
 pub mod surreal_data_structs;
+pub mod tariffs;
 pub mod time_processes;
 use csv::Writer;
+use finance::{FinancialParams, FinancialResult};
 use plotters::prelude::*;
 use std::error::Error;
 use std::fs::OpenOptions;
 use std::io::Write;
 use std::path::Path;
 use surreal_data_structs::*;
+use tariffs::{MonthlyBill, Tariff};
 
 // Define your data structure
-pub type StatData = (usize, f64, f64, f32, f32, f64);
-pub type Data = (DateTime<Utc>, f32, f32, f32, bool);
+/// One row of the specification stats file.
+pub struct StatData {
+    pub chargers_count: usize,
+    pub energy_system_size: f64,
+    pub battery_size: f64,
+    pub duration_energy_needed: f32,
+    pub max_output: f32,
+    pub average_usage: f64,
+    pub state_of_health: f32,
+    pub end_of_life: String,
+    pub site: SiteStats,
+}
+/// Site-level results of a run, written next to the battery columns of the stats row.
+pub struct SiteStats {
+    pub dispatch_strategy: String,
+    pub energy_not_served_wh: f32,
+    pub grid_bill: f64,
+    pub peak_charger_kva: f32,
+    pub transformer_overload_hours: usize,
+    /// Fleet vehicles that left below their required energy; zero without a fleet schedule.
+    pub undercharged_vehicles: usize,
+}
+pub type Data = (DateTime<Utc>, f32, f32, f32, bool, f32, f32, f32);
 pub type Lambdas = Vec<f64>;
+/// One row of the finance stats file.
+pub struct FinanceData {
+    pub run_id: usize,
+    pub chargers_count: usize,
+    pub fleet: String,
+    pub energy_system_size: f64,
+    pub pv_mounting: String,
+    pub pv_energy_kwh: f64,
+    pub pv_clipped_kwh: f64,
+    pub battery_size: f64,
+    pub dispatch_strategy: String,
+    pub result: FinancialResult,
+}
+/// One row of the queue stats file.
+pub struct QueueData {
+    pub run_id: usize,
+    pub chargers_count: usize,
+    pub fleet: String,
+    pub energy_system_size: f64,
+    pub battery_size: f64,
+    pub dispatch_strategy: String,
+    pub metrics: QueueMetrics,
+    pub availability: AvailabilityMetrics,
+}
+/// One row of the smart charging comparison file.
+pub struct ComparisonData {
+    pub run_id: usize,
+    pub fleet: String,
+    pub energy_system_size: f64,
+    pub comparison: ChargingComparison,
+}
+/// Settings shared by every run of a sweep, and the values swept.
+pub struct SimulationConfig {
+    /// Start and end of the simulated period, as "%Y-%m-%d %H:%M:%S%:z".
+    pub start_date: String,
+    pub end_date: String,
+    /// Battery power rating in watts and hours of storage at that rating.
+    pub battery_params: Vec<(f32, f32)>,
+    /// Array rows, panels per row, panel watts and mounting.
+    pub pv_params: Vec<(usize, usize, f32, Mounting)>,
+    pub fleets: Vec<FleetMix>,
+    /// Expected vehicle arrivals in each hour of the day.
+    pub lambdas: Vec<Lambdas>,
+    pub strategies: Vec<Box<dyn DispatchStrategy>>,
+    pub grid: GridConnection,
+    pub finance: FinancialParams,
+    pub station: StationConfig,
+}
+
+impl SimulationConfig {
+    /// The settings for run `run_id` with the given dispatch strategy.
+    pub fn run_config<'a>(
+        &'a self,
+        run_id: usize,
+        strategy: &'a dyn DispatchStrategy,
+        scenario: &'a Scenario,
+    ) -> RunConfig<'a> {
+        RunConfig {
+            run_id,
+            strategy,
+            scenario,
+            grid: &self.grid,
+            finance: &self.finance,
+            station: &self.station,
+        }
+    }
+}
+
+/// Vehicles and simulator seed drawn once per sweep cell, so every dispatch strategy sees the
+/// same arrivals, charger outages and drivers.
+pub struct Scenario {
+    pub vehicles: Vec<Vehicle>,
+    pub seed: u64,
+}
+
+impl Scenario {
+    /// Takes the vehicles from the station's fleet schedule, or samples arrivals at the hourly
+    /// `lambda` rates between `date1` and `date2` when there is none.
+    pub async fn sample(
+        station: &StationConfig,
+        lambda: Vec<f64>,
+        date1: &str,
+        date2: &str,
+    ) -> Result<Scenario, anyhow::Error> {
+        let vehicles = match &station.fleet_schedule {
+            Some(schedule) => schedule.vehicles(&station.vehicle_profiles)?,
+            None => {
+                let arrivals = sample_arrival_times(date1, date2, lambda).await?;
+                sample_vehicles(&arrivals, &station.vehicle_profiles)
+            }
+        };
+        Ok(Scenario {
+            vehicles,
+            seed: rand::random(),
+        })
+    }
+}
+
+/// Settings for a single run: one dispatch strategy and scenario with the shared grid, finance
+/// and station.
+pub struct RunConfig<'a> {
+    /// Sweep index of the run, keying its rows in per-run output files.
+    pub run_id: usize,
+    pub strategy: &'a dyn DispatchStrategy,
+    pub scenario: &'a Scenario,
+    pub grid: &'a GridConnection,
+    pub finance: &'a FinancialParams,
+    pub station: &'a StationConfig,
+}
 pub fn create_stat_csv(file_path: &str) -> Result<(), Box<dyn Error>> {
     let mut wtr = Writer::from_path(file_path)?;
 
@@ -33,6 +180,14 @@ pub fn create_stat_csv(file_path: &str) -> Result<(), Box<dyn Error>> {
         "Duration Energy % Needed by Grid",
         "max_output",
         "average usage",
+        "State of Health",
+        "End of Life",
+        "Dispatch Strategy",
+        "Energy Not Served",
+        "Grid Bill",
+        "Peak Charger kVA",
+        "Transformer Overload Hours",
+        "Undercharged Vehicles",
     ])?;
 
     // Flush the writer to ensure the header is written
@@ -45,22 +200,22 @@ pub fn append_to_stat_csv(data: Vec<StatData>, file_path: &str) -> Result<(), Bo
         let file = OpenOptions::new().append(true).open(file_path)?;
         let mut wtr = Writer::from_writer(file);
         // Write the data
-        for (
-            chargers_count,
-            energy_system_size,
-            battery_size,
-            duration_energy_needed,
-            max_output,
-            average_usage,
-        ) in data
-        {
+        for row in data {
             wtr.write_record(&[
-                chargers_count.to_string(),
-                energy_system_size.to_string(),
-                battery_size.to_string(),
-                duration_energy_needed.to_string(),
-                max_output.to_string(),
-                average_usage.to_string(),
+                row.chargers_count.to_string(),
+                row.energy_system_size.to_string(),
+                row.battery_size.to_string(),
+                row.duration_energy_needed.to_string(),
+                row.max_output.to_string(),
+                row.average_usage.to_string(),
+                row.state_of_health.to_string(),
+                row.end_of_life,
+                row.site.dispatch_strategy,
+                row.site.energy_not_served_wh.to_string(),
+                row.site.grid_bill.to_string(),
+                row.site.peak_charger_kva.to_string(),
+                row.site.transformer_overload_hours.to_string(),
+                row.site.undercharged_vehicles.to_string(),
             ])?;
         }
 
@@ -72,22 +227,22 @@ pub fn append_to_stat_csv(data: Vec<StatData>, file_path: &str) -> Result<(), Bo
         let mut wtr = Writer::from_writer(file);
 
         // Write the data
-        for (
-            chargers_count,
-            energy_system_size,
-            battery_size,
-            duration_energy_needed,
-            max_output,
-            average_usage,
-        ) in data
-        {
+        for row in data {
             wtr.write_record(&[
-                chargers_count.to_string(),
-                energy_system_size.to_string(),
-                battery_size.to_string(),
-                duration_energy_needed.to_string(),
-                max_output.to_string(),
-                average_usage.to_string(),
+                row.chargers_count.to_string(),
+                row.energy_system_size.to_string(),
+                row.battery_size.to_string(),
+                row.duration_energy_needed.to_string(),
+                row.max_output.to_string(),
+                row.average_usage.to_string(),
+                row.state_of_health.to_string(),
+                row.end_of_life,
+                row.site.dispatch_strategy,
+                row.site.energy_not_served_wh.to_string(),
+                row.site.grid_bill.to_string(),
+                row.site.peak_charger_kva.to_string(),
+                row.site.transformer_overload_hours.to_string(),
+                row.site.undercharged_vehicles.to_string(),
             ])?;
         }
         // Flush the writer to ensure all data is written
@@ -96,6 +251,336 @@ pub fn append_to_stat_csv(data: Vec<StatData>, file_path: &str) -> Result<(), Bo
     Ok(())
 }
 
+pub fn append_to_finance_csv(
+    data: Vec<FinanceData>,
+    file_path: &str,
+) -> Result<(), Box<dyn Error>> {
+    let exists = Path::new(file_path).exists();
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file_path)?;
+    let mut wtr = Writer::from_writer(file);
+    if !exists {
+        wtr.write_record([
+            "Run",
+            "Chargers Count",
+            "Fleet",
+            "Energy System Size",
+            "PV Mounting",
+            "PV Energy kWh",
+            "PV Clipped kWh",
+            "Battery Size",
+            "Dispatch Strategy",
+            "Capex",
+            "Annual Revenue",
+            "Annual Opex",
+            "Annual Energy kWh",
+            "NPV",
+            "IRR",
+            "Payback Years",
+            "LCOE",
+        ])?;
+    }
+    for row in data {
+        let result = row.result;
+        wtr.write_record(&[
+            row.run_id.to_string(),
+            row.chargers_count.to_string(),
+            row.fleet,
+            row.energy_system_size.to_string(),
+            row.pv_mounting,
+            row.pv_energy_kwh.to_string(),
+            row.pv_clipped_kwh.to_string(),
+            row.battery_size.to_string(),
+            row.dispatch_strategy,
+            result.capex.to_string(),
+            result.annual_revenue.to_string(),
+            result.annual_opex.to_string(),
+            result.annual_energy_kwh.to_string(),
+            result.npv.to_string(),
+            result.irr.map(|irr| irr.to_string()).unwrap_or_default(),
+            result
+                .payback_years
+                .map(|years| years.to_string())
+                .unwrap_or_default(),
+            result.lcoe.to_string(),
+        ])?;
+    }
+    // Flush the writer to ensure all data is written
+    wtr.flush()?;
+    Ok(())
+}
+
+pub fn append_to_queue_csv(data: Vec<QueueData>, file_path: &str) -> Result<(), Box<dyn Error>> {
+    let exists = Path::new(file_path).exists();
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file_path)?;
+    let mut wtr = Writer::from_writer(file);
+    if !exists {
+        wtr.write_record([
+            "Run",
+            "Chargers Count",
+            "Fleet",
+            "Energy System Size",
+            "Battery Size",
+            "Dispatch Strategy",
+            "Arrivals",
+            "Charged",
+            "Balked",
+            "Reneged",
+            "Mean Wait Minutes",
+            "P95 Wait Minutes",
+            "Mean Utilization",
+            "Mean Uptime",
+            "Outages",
+            "Interrupted",
+            "Lost Demand Wh",
+        ])?;
+    }
+    for row in data {
+        let (metrics, availability) = (row.metrics, row.availability);
+        wtr.write_record(&[
+            row.run_id.to_string(),
+            row.chargers_count.to_string(),
+            row.fleet,
+            row.energy_system_size.to_string(),
+            row.battery_size.to_string(),
+            row.dispatch_strategy,
+            metrics.arrivals.to_string(),
+            metrics.charged.to_string(),
+            metrics.balked.to_string(),
+            metrics.reneged.to_string(),
+            metrics.mean_wait_minutes.to_string(),
+            metrics.p95_wait_minutes.to_string(),
+            metrics.mean_utilization().to_string(),
+            availability.mean_uptime().to_string(),
+            availability.outages.to_string(),
+            availability.interrupted_sessions.to_string(),
+            availability.lost_demand_wh.to_string(),
+        ])?;
+    }
+    // Flush the writer to ensure all data is written
+    wtr.flush()?;
+    Ok(())
+}
+
+pub fn append_to_comparison_csv(
+    data: Vec<ComparisonData>,
+    file_path: &str,
+) -> Result<(), Box<dyn Error>> {
+    let exists = Path::new(file_path).exists();
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file_path)?;
+    let mut wtr = Writer::from_writer(file);
+    if !exists {
+        wtr.write_record([
+            "Run",
+            "Fleet",
+            "Energy System Size",
+            "Mode",
+            "Energy Delivered",
+            "PV Self Consumed",
+            "Grid Import",
+            "Peak Draw",
+            "Energy Cost",
+            "Sessions Below Target",
+        ])?;
+    }
+    for row in data {
+        let comparison = row.comparison;
+        wtr.write_record(&[
+            row.run_id.to_string(),
+            row.fleet,
+            row.energy_system_size.to_string(),
+            comparison.mode,
+            comparison.energy_delivered_wh.to_string(),
+            comparison.pv_self_consumed_wh.to_string(),
+            comparison.grid_import_wh.to_string(),
+            comparison.peak_draw_w.to_string(),
+            comparison.energy_cost.to_string(),
+            comparison.sessions_below_target.to_string(),
+        ])?;
+    }
+    // Flush the writer to ensure all data is written
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Appends one run's per-charger utilization and uptime, keyed by run id.
+pub fn append_to_utilization_csv(
+    run_id: usize,
+    metrics: &QueueMetrics,
+    availability: &AvailabilityMetrics,
+    file_path: &str,
+) -> Result<(), Box<dyn Error>> {
+    let exists = Path::new(file_path).exists();
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file_path)?;
+    let mut wtr = Writer::from_writer(file);
+    if !exists {
+        wtr.write_record(["Run", "Charger", "Utilization", "Uptime"])?;
+    }
+    for (idx, (utilization, uptime)) in metrics
+        .charger_utilization
+        .iter()
+        .zip(availability.charger_uptime.iter())
+        .enumerate()
+    {
+        wtr.write_record(&[
+            run_id.to_string(),
+            idx.to_string(),
+            utilization.to_string(),
+            uptime.to_string(),
+        ])?;
+    }
+    // Flush the writer to ensure all data is written
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Appends one run's station meter broken into energy delivered to vehicles, conversion loss
+/// and standby draw, with V2G export alongside, keyed by run id. Delivered energy is what is
+/// left of the metered input after loss and standby.
+pub fn append_to_station_energy_csv(
+    run_id: usize,
+    demand: &PowerComponent,
+    file_path: &str,
+) -> Result<(), Box<dyn Error>> {
+    let (Some(input), Some(loss), Some(standby)) = (
+        demand.input_power_w_ts.as_ref(),
+        demand.loss_w_ts.as_ref(),
+        demand.standby_w_ts.as_ref(),
+    ) else {
+        return Err("station demand is missing its loss or standby series".into());
+    };
+    let exists = Path::new(file_path).exists();
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file_path)?;
+    let mut wtr = Writer::from_writer(file);
+    if !exists {
+        wtr.write_record([
+            "Run",
+            "Date",
+            "Metered Input W",
+            "Delivered W",
+            "Conversion Loss W",
+            "Standby W",
+            "Exported W",
+        ])?;
+    }
+    for (idx, ((date, input_w), ((_, loss_w), (_, standby_w)))) in input
+        .iter()
+        .zip(loss.iter().zip(standby.iter()))
+        .enumerate()
+    {
+        let export_w = demand
+            .export_w_ts
+            .as_ref()
+            .map_or(0.0, |export| export[idx].1);
+        wtr.write_record(&[
+            run_id.to_string(),
+            date.to_rfc3339(),
+            input_w.to_string(),
+            (input_w - loss_w - standby_w).to_string(),
+            loss_w.to_string(),
+            standby_w.to_string(),
+            export_w.to_string(),
+        ])?;
+    }
+    // Flush the writer to ensure all data is written
+    wtr.flush()?;
+    Ok(())
+}
+
+pub fn write_demand_csv(
+    data: &[(DateTime<Utc>, f32)],
+    file_path: &str,
+) -> Result<(), Box<dyn Error>> {
+    let mut wtr = Writer::from_path(file_path)?;
+
+    // Write the header
+    wtr.write_record(["Date", "Demand W"])?;
+
+    // Write the data
+    for (date, demand_w) in data {
+        wtr.write_record(&[date.to_rfc3339(), demand_w.to_string()])?;
+    }
+    // Flush the writer to ensure all data is written
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Appends one run's hourly clipped PV power, keyed by the run id used in the finance stats.
+pub fn append_to_clipping_csv(
+    run_id: usize,
+    data: &[(DateTime<Utc>, f32)],
+    file_path: &str,
+) -> Result<(), Box<dyn Error>> {
+    let exists = Path::new(file_path).exists();
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file_path)?;
+    let mut wtr = Writer::from_writer(file);
+    if !exists {
+        wtr.write_record(["Run", "Date", "Clipped W"])?;
+    }
+    for (date, clipped_w) in data {
+        wtr.write_record(&[run_id.to_string(), date.to_rfc3339(), clipped_w.to_string()])?;
+    }
+    // Flush the writer to ensure all data is written
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Appends one run's hourly real, reactive and apparent charger power, keyed by run id.
+pub fn append_to_site_power_csv(
+    run_id: usize,
+    data: &SitePower,
+    file_path: &str,
+) -> Result<(), Box<dyn Error>> {
+    let exists = Path::new(file_path).exists();
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file_path)?;
+    let mut wtr = Writer::from_writer(file);
+    if !exists {
+        wtr.write_record([
+            "Run",
+            "Date",
+            "Real Power W",
+            "Reactive Power VAR",
+            "Apparent Power VA",
+        ])?;
+    }
+    for ((date, real_w), ((_, reactive_var), (_, apparent_va))) in data.real_power_w_ts.iter().zip(
+        data.reactive_power_var_ts
+            .iter()
+            .zip(data.apparent_power_va_ts.iter()),
+    ) {
+        wtr.write_record(&[
+            run_id.to_string(),
+            date.to_rfc3339(),
+            real_w.to_string(),
+            reactive_var.to_string(),
+            apparent_va.to_string(),
+        ])?;
+    }
+    // Flush the writer to ensure all data is written
+    wtr.flush()?;
+    Ok(())
+}
+
 pub fn write_to_csv(data: Vec<Data>, file_path: &str) -> Result<(), Box<dyn Error>> {
     let mut wtr = Writer::from_path(file_path)?;
 
@@ -106,16 +591,194 @@ pub fn write_to_csv(data: Vec<Data>, file_path: &str) -> Result<(), Box<dyn Erro
         "Input Power",
         "Output Power",
         "Negative Net Storage",
+        "Grid Import",
+        "Curtailment",
+        "State of Health",
     ])?;
 
     // Write the data
-    for (datetime, storage, input_power, output_power, dummy) in data {
+    for (
+        datetime,
+        storage,
+        input_power,
+        output_power,
+        dummy,
+        grid_import,
+        curtailment,
+        state_of_health,
+    ) in data
+    {
         wtr.write_record(&[
             datetime.to_rfc3339(),
             storage.to_string(),
             input_power.to_string(),
             output_power.to_string(),
             dummy.to_string(),
+            grid_import.to_string(),
+            curtailment.to_string(),
+            state_of_health.to_string(),
+        ])?;
+    }
+    // Flush the writer to ensure all data is written
+    wtr.flush()?;
+    Ok(())
+}
+
+pub fn append_to_bill_csv(
+    run_id: usize,
+    data: Vec<MonthlyBill>,
+    file_path: &str,
+) -> Result<(), Box<dyn Error>> {
+    let exists = Path::new(file_path).exists();
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file_path)?;
+    let mut wtr = Writer::from_writer(file);
+    if !exists {
+        wtr.write_record([
+            "Run",
+            "Year",
+            "Month",
+            "Import kWh",
+            "Energy Charge",
+            "Peak Demand kW",
+            "Demand Charge",
+            "Fixed Charge",
+            "Export kWh",
+            "Export Credit",
+            "Total",
+        ])?;
+    }
+
+    // Write the data
+    for bill in data {
+        wtr.write_record(&[
+            run_id.to_string(),
+            bill.year.to_string(),
+            bill.month.to_string(),
+            bill.import_kwh.to_string(),
+            bill.energy_charge.to_string(),
+            bill.peak_demand_kw.to_string(),
+            bill.demand_charge.to_string(),
+            bill.fixed_charge.to_string(),
+            bill.export_kwh.to_string(),
+            bill.export_credit.to_string(),
+            bill.total.to_string(),
+        ])?;
+    }
+    // Flush the writer to ensure all data is written
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Appends one run's charging sessions, keyed by run id.
+pub fn append_to_sessions_csv(
+    run_id: usize,
+    data: &[EvSession],
+    file_path: &str,
+) -> Result<(), Box<dyn Error>> {
+    let exists = Path::new(file_path).exists();
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file_path)?;
+    let mut wtr = Writer::from_writer(file);
+    if !exists {
+        wtr.write_record([
+            "Run",
+            "Vehicle",
+            "Profile",
+            "Status",
+            "Charger",
+            "Charger Model",
+            "Arrival",
+            "Plug In",
+            "Departure",
+            "Wait Minutes",
+            "Energy Requested",
+            "Energy Delivered",
+            "Energy Discharged",
+            "Outage Affected",
+            "Arrival SoC",
+            "Departure SoC",
+            "Target SoC",
+        ])?;
+    }
+    for session in data {
+        wtr.write_record(&[
+            run_id.to_string(),
+            session.vehicle_id.to_string(),
+            session.profile.clone(),
+            format!("{:?}", session.status),
+            session
+                .charger_index
+                .map(|idx| idx.to_string())
+                .unwrap_or_default(),
+            session.charger_model.clone().unwrap_or_default(),
+            session.arrival.to_rfc3339(),
+            session
+                .plug_in
+                .map(|date| date.to_rfc3339())
+                .unwrap_or_default(),
+            session
+                .departure
+                .map(|date| date.to_rfc3339())
+                .unwrap_or_default(),
+            session.wait_minutes.to_string(),
+            session.energy_requested_wh.to_string(),
+            session.energy_delivered_wh.to_string(),
+            session.energy_discharged_wh.to_string(),
+            session.outage_affected.to_string(),
+            session.arrival_soc.to_string(),
+            session.departure_soc.to_string(),
+            session.target_soc.to_string(),
+        ])?;
+    }
+    // Flush the writer to ensure all data is written
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Appends one run's depot schedule outcomes, keyed by run id.
+pub fn append_to_depot_csv(
+    run_id: usize,
+    data: &[DepotOutcome],
+    file_path: &str,
+) -> Result<(), Box<dyn Error>> {
+    let exists = Path::new(file_path).exists();
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file_path)?;
+    let mut wtr = Writer::from_writer(file);
+    if !exists {
+        wtr.write_record([
+            "Run",
+            "Vehicle",
+            "Return",
+            "Departure",
+            "Charger",
+            "Charger Model",
+            "Energy Required",
+            "Energy Delivered",
+            "Undercharged",
+        ])?;
+    }
+    for outcome in data {
+        wtr.write_record(&[
+            run_id.to_string(),
+            outcome.vehicle_id.clone(),
+            outcome.return_time.to_rfc3339(),
+            outcome.departure_time.to_rfc3339(),
+            outcome
+                .charger_index
+                .map(|idx| idx.to_string())
+                .unwrap_or_default(),
+            outcome.charger_model.clone().unwrap_or_default(),
+            outcome.required_energy_wh.to_string(),
+            outcome.energy_delivered_wh.to_string(),
+            outcome.undercharged.to_string(),
         ])?;
     }
     // Flush the writer to ensure all data is written
@@ -247,65 +910,216 @@ pub async fn run_simulation(
     date2: &str,
     datab: &Surreal<Client>,
     site: &str,
+    config: &RunConfig<'_>,
 ) -> Result<(), anyhow::Error> {
+    let RunConfig {
+        strategy,
+        scenario,
+        grid,
+        finance,
+        station,
+        ..
+    } = *config;
     //Simulate the process
-    let df = modulated_markov_poison_process(date1, date2, lambda.clone()).await?;
-    let time_matrix = df.0;
-    let date_matrix = df.2;
-    let mut i = 0;
-    let _production_time = (0..time_matrix.ncols())
-        .map(|col| time_matrix.column(col).sum())
-        .collect::<Vec<f64>>();
-    let mut charging_station = charging_station
-        .iter_mut()
-        .map(|charger| {
-            let mut j = 0;
-            charger.power.input_power_w_ts = Some(
-                charger
-                    .clone()
-                    .power
-                    .input_power_w_ts
-                    .unwrap()
-                    .iter_mut()
-                    .map(|(dt, pow)| {
-                        let power_output = (*pow) * (time_matrix[(i, j)] as f32);
-                        let temp = date_matrix[(i, j)];
-                        if i % 23 == 0 && i != 0 {
-                            j += 1;
-                            i = 0;
-                        } else {
-                            i += 1;
-                        }
-                        match true {
-                            _ if &temp == dt => (), //println!("{:?}", "This matched"),
-                            _ => println!("{:?}", "Doesn't match"),
-                        }
-                        (*dt, power_output)
-                    })
-                    .collect::<Vec<(DateTime<Utc>, f32)>>(),
-            );
-            charger
-        })
-        .collect::<Vec<&mut Charger>>();
-    // ... further result processing and output
-    // Optionally plot the results to a PNG file
+    let vehicles = &scenario.vehicles;
+    let mut simulator = SessionSimulator::new(date1, date2)?
+        .with_seed(scenario.seed)
+        .with_queue_policy(station.queue_policy.clone());
+    if let Some(load_manager) = &station.load_manager {
+        simulator = simulator.with_load_manager(
+            load_manager
+                .clone()
+                .with_available_power(&base_photovoltaic, grid),
+        );
+    }
+    // V2G is decided here, in its own pass before the battery is dispatched; the battery then
+    // sees the station draw net of the vehicles' export.
+    if let Some(v2g) = &station.v2g {
+        simulator = simulator.with_v2g(v2g.clone().with_generation(&base_photovoltaic));
+    }
+    let uncontrolled = simulator.clone();
+    if let Some(smart_charging) = &station.smart_charging {
+        let mut smart_charging = smart_charging.clone().with_generation(&base_photovoltaic);
+        if let Some(tariff) = &grid.tariff {
+            smart_charging = smart_charging.with_tariff(tariff.clone());
+        }
+        simulator = simulator.with_smart_charging(smart_charging);
+    }
+    let sessions = simulator.run(charging_station, vehicles);
+    append_to_sessions_csv(config.run_id, &sessions, "sessions.csv")
+        .map_err(|err| anyhow::anyhow!("writing sessions.csv: {err}"))?;
+    let mut undercharged_vehicles = 0;
+    if let Some(schedule) = &station.fleet_schedule {
+        let outcomes = schedule.outcomes(&sessions);
+        append_to_depot_csv(config.run_id, &outcomes, "depot_schedule.csv")
+            .map_err(|err| anyhow::anyhow!("writing depot_schedule.csv: {err}"))?;
+        undercharged_vehicles = outcomes
+            .iter()
+            .filter(|outcome| outcome.undercharged)
+            .count();
+    }
+    if let Some(smart_charging) = &station.smart_charging {
+        // Same vehicles through the same fleet with every session charging on arrival. The
+        // clone shares the seed, so outages and driver patience match the managed run.
+        let mut uncontrolled_station = charging_station.clone();
+        let uncontrolled_sessions = uncontrolled.run(&mut uncontrolled_station, vehicles);
+        append_to_comparison_csv(
+            vec![
+                ComparisonData {
+                    run_id: config.run_id,
+                    fleet: fleet_label(&fleet_mix(charging_station)),
+                    energy_system_size: (base_photovoltaic.num_panels
+                        * base_photovoltaic.panel_watts)
+                        .into(),
+                    comparison: ChargingComparison::from_run(
+                        &format!("{:?}", smart_charging.objective),
+                        charging_station,
+                        &sessions,
+                        &base_photovoltaic,
+                        grid.tariff.as_ref(),
+                    ),
+                },
+                ComparisonData {
+                    run_id: config.run_id,
+                    fleet: fleet_label(&fleet_mix(charging_station)),
+                    energy_system_size: (base_photovoltaic.num_panels
+                        * base_photovoltaic.panel_watts)
+                        .into(),
+                    comparison: ChargingComparison::from_run(
+                        "Uncontrolled",
+                        &uncontrolled_station,
+                        &uncontrolled_sessions,
+                        &base_photovoltaic,
+                        grid.tariff.as_ref(),
+                    ),
+                },
+            ],
+            "smart_charging_comparison.csv",
+        )
+        .map_err(|err| anyhow::anyhow!("writing smart_charging_comparison.csv: {err}"))?;
+    }
+    let queue_metrics = QueueMetrics::from_sessions(
+        &sessions,
+        charging_station.len(),
+        simulator.start,
+        simulator.end,
+    );
+    let availability =
+        AvailabilityMetrics::from_run(charging_station, &sessions, simulator.start, simulator.end);
+    append_to_utilization_csv(
+        config.run_id,
+        &queue_metrics,
+        &availability,
+        "charger_utilization.csv",
+    )
+    .map_err(|err| anyhow::anyhow!("writing charger_utilization.csv: {err}"))?;
     let power_component_vec: Vec<PowerComponent> = charging_station
-        .iter_mut()
+        .iter()
         .map(|charger| charger.power.clone())
         .collect();
     let charging_station_comp = PowerComponent::merge_power_components(power_component_vec, 1.0);
+    append_to_station_energy_csv(
+        config.run_id,
+        &charging_station_comp.demand,
+        "station_energy.csv",
+    )
+    .map_err(|err| anyhow::anyhow!("writing station_energy.csv: {err}"))?;
+    let site_power = SitePower::from_chargers(charging_station);
+    append_to_site_power_csv(config.run_id, &site_power, "site_power.csv")
+        .map_err(|err| anyhow::anyhow!("writing site_power.csv: {err}"))?;
+    // Exports net against the station's draw at the site bus.
+    let site_demand = PowerComponent::new_ts(charging_station_comp.demand.net_input_w_ts(), None);
+    let clipped_w_ts = base_photovoltaic.clipped_power_w_ts();
+    let pv_clipped_kwh = clipped_w_ts.iter().map(|(_, w)| *w as f64).sum::<f64>() / 1000.0;
+    if base_photovoltaic.inverter.is_some() {
+        append_to_clipping_csv(config.run_id, &clipped_w_ts, "pv_clipping.csv")
+            .map_err(|err| anyhow::anyhow!("writing pv_clipping.csv: {err}"))?;
+    }
     let generator = base_photovoltaic.clone().into_power_component();
+    let pv_energy_kwh = generator
+        .output_power_w_ts
+        .iter()
+        .flatten()
+        .map(|(_, w)| *w as f64)
+        .sum::<f64>()
+        / 1000.0;
     // Update the BatteryStorage instance with the new power component data
     //println!("{:?}", generator.clone() );
-    *battery_storage = battery_storage
+    *battery_storage = battery_storage.clone().dispatch(
+        // The inverter's night tare comes off what the array feeds the bus.
+        PowerComponent::new_ts(None, generator.net_output_w_ts()),
+        site_demand,
+        &clipped_w_ts,
+        base_photovoltaic
+            .inverter
+            .as_ref()
+            .map(|inverter| inverter.ac_rating_w),
+        strategy,
+    );
+    let grid = grid
         .clone()
-        .update_power_component(generator, charging_station_comp.demand);
+        .update_power_component(&battery_storage.battery_state);
     sim_to_csv(&mut battery_storage.clone());
+    if grid.tariff.is_some() {
+        append_to_bill_csv(config.run_id, grid.monthly_bills(), "monthly_bill.csv")
+            .map_err(|err| anyhow::anyhow!("writing monthly_bill.csv: {err}"))?;
+    }
+    let simulated_hours = (simulator.end - simulator.start).num_seconds() as f64 / 3600.0;
+    let financial_result = finance.evaluate(
+        finance.capex(&base_photovoltaic, battery_storage, charging_station),
+        simulated_hours,
+        sessions
+            .iter()
+            .map(|session| session.energy_delivered_wh as f64)
+            .sum::<f64>()
+            / 1000.0,
+        Tariff::total_bill(&grid.monthly_bills()),
+    );
+    append_to_finance_csv(
+        vec![FinanceData {
+            run_id: config.run_id,
+            chargers_count: charging_station.len(),
+            fleet: fleet_label(&fleet_mix(charging_station)),
+            energy_system_size: (base_photovoltaic.num_panels * base_photovoltaic.panel_watts)
+                .into(),
+            pv_mounting: base_photovoltaic.mounting_label().to_string(),
+            pv_energy_kwh,
+            pv_clipped_kwh,
+            battery_size: battery_storage.full_capacity_wh().into(),
+            dispatch_strategy: strategy.name(),
+            result: financial_result,
+        }],
+        "finance_stat.csv",
+    )
+    .map_err(|err| anyhow::anyhow!("writing finance_stat.csv: {err}"))?;
+    append_to_queue_csv(
+        vec![QueueData {
+            run_id: config.run_id,
+            chargers_count: charging_station.len(),
+            fleet: fleet_label(&fleet_mix(charging_station)),
+            energy_system_size: (base_photovoltaic.num_panels * base_photovoltaic.panel_watts)
+                .into(),
+            battery_size: battery_storage.full_capacity_wh().into(),
+            dispatch_strategy: strategy.name(),
+            metrics: queue_metrics,
+            availability,
+        }],
+        "queue_stat.csv",
+    )
+    .map_err(|err| anyhow::anyhow!("writing queue_stat.csv: {err}"))?;
     _ = gen_stat(
         &mut battery_storage.clone(),
         base_photovoltaic,
-        charging_station,
+        charging_station.iter_mut().collect(),
         lambda,
+        SiteStats {
+            dispatch_strategy: strategy.name(),
+            energy_not_served_wh: grid.energy_not_served_wh(),
+            grid_bill: Tariff::total_bill(&grid.monthly_bills()),
+            peak_charger_kva: site_power.peak_apparent_kva(),
+            transformer_overload_hours: site_power.overload_hours(grid.transformer_rating_kva),
+            undercharged_vehicles,
+        },
         "stat_df.csv",
     );
     _ = battery_storage_to_db(&mut battery_storage.clone(), datab, site).await?;
@@ -340,13 +1154,35 @@ pub fn sim_to_csv(batt_system: &mut BatteryStorage) {
         .neg_stat_ts
         .unwrap()
         .into_iter();
+    let grid_import_iter = batt_system
+        .clone()
+        .battery_state
+        .grid_import_w_ts
+        .unwrap()
+        .into_iter();
+    let curtailment_iter = batt_system
+        .clone()
+        .battery_state
+        .curtailment_w_ts
+        .unwrap()
+        .into_iter();
+    let health_iter = batt_system
+        .clone()
+        .battery_state
+        .state_of_health_ts
+        .unwrap()
+        .into_iter();
     let data: Vec<_> = storage_iter
         .zip(input_iter.zip(output_iter.zip(neg_stat_iter)))
+        .zip(grid_import_iter.zip(curtailment_iter.zip(health_iter)))
         .map(
-            |((s_date, s_val), ((i_date, i_val), ((o_date, o_val), (_j_date, j_val))))| {
+            |(
+                ((s_date, s_val), ((i_date, i_val), ((o_date, o_val), (_j_date, j_val)))),
+                ((_g_date, g_val), ((_c_date, c_val), (_h_date, h_val))),
+            )| {
                 assert_eq!(s_date, i_date);
                 assert_eq!(s_date, o_date);
-                (s_date, s_val, i_val, o_val, j_val)
+                (s_date, s_val, i_val, o_val, j_val, g_val, c_val, h_val)
             },
         )
         .collect();
@@ -358,6 +1194,7 @@ pub fn gen_stat(
     pv_system: PvSystem,
     ev_chargers: Vec<&mut Charger>,
     lamb_vec: Vec<f64>,
+    site: SiteStats,
     file_path: &str,
 ) -> std::result::Result<(), std::boxed::Box<(dyn std::error::Error + 'static)>> {
     fn sum_bools(bools: &Vec<bool>) -> usize {
@@ -421,30 +1258,48 @@ pub fn gen_stat(
     //println!("{:?}", sum_bools(&data_neg_stat));
     //println!("{:?}", (sum_bools(&data_neg_stat) as f32)/(data.len() as f32));
     append_to_stat_csv(
-        vec![(
-            ev_chargers.len(),
-            (pv_system.num_panels * pv_system.panel_watts).into(),
-            (batt_system.capacity * batt_system.watt_hours).into(),
-            (sum_bools(&data_neg_stat) as f32) / (data.len() as f32),
-            max_f32_in_vec(&data_storage).expect("REASON"),
-            lamb_vec.iter().sum::<f64>() / 60.0,
-        )],
+        vec![StatData {
+            chargers_count: ev_chargers.len(),
+            energy_system_size: (pv_system.num_panels * pv_system.panel_watts).into(),
+            battery_size: (batt_system.capacity * batt_system.watt_hours).into(),
+            duration_energy_needed: (sum_bools(&data_neg_stat) as f32) / (data.len() as f32),
+            max_output: max_f32_in_vec(&data_storage).expect("REASON"),
+            average_usage: lamb_vec.iter().sum::<f64>() / 60.0,
+            state_of_health: batt_system
+                .battery_state
+                .state_of_health_ts
+                .as_ref()
+                .and_then(|health| health.last())
+                .map_or(100.0, |(_, soh)| *soh),
+            end_of_life: batt_system
+                .end_of_life_date()
+                .map(|date| date.to_rfc3339())
+                .unwrap_or_default(),
+            site,
+        }],
         file_path,
     )
 }
 
 pub async fn setup_and_run_simulation(
     db: &Surreal<Client>,
-    template_charger_180kw: &mut Charger,
+    charger_catalog: &ChargerCatalog,
     solar_system: PvSystem,
     battery_storage: &mut BatteryStorage,
-    start_date: &str,
-    end_date: &str,
-    battery_param_vec: Vec<(f32, f32)>,
-    pv_param_vec: Vec<(usize, usize, f32)>,
-    ev_charger_param_vec: Vec<usize>,
-    lamb_vec: Vec<Lambdas>,
+    config: SimulationConfig,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let start_date = config.start_date.as_str();
+    let end_date = config.end_date.as_str();
+    // The first run uses the leading entry of each sweep.
+    if config.strategies.is_empty() {
+        return Err("at least one dispatch strategy is required".into());
+    }
+    if config.fleets.is_empty() {
+        return Err("at least one charger fleet mix is required".into());
+    }
+    if config.lambdas.is_empty() {
+        return Err("at least one arrival rate profile is required".into());
+    }
     // Setup SurrealDB entry
     let _temp: Option<EvPvLdes> = db
         .create((
@@ -462,55 +1317,96 @@ pub async fn setup_and_run_simulation(
         .unwrap();
     println!("got here");
 
-    let _ = template_charger_180kw.add_input_power_ts(start_date, end_date);
-    let mut charging_station: Vec<Charger> = vec![template_charger_180kw.clone(); 20];
+    // Sweep variants keep the base system's irradiance, panel, conversion losses and
+    // temperature model.
+    let base_pv = solar_system.clone();
+    // Sweep batteries keep the base battery's coupling, efficiencies and degradation model.
+    let base_battery = battery_storage.clone();
+    let mut charging_station: Vec<Charger> = charger_catalog.build_fleet(&config.fleets[0])?;
     let _ = create_stat_csv("specification_neg_stat.csv");
 
+    let scenario = Scenario::sample(
+        &config.station,
+        config.lambdas[0].to_vec(),
+        start_date,
+        end_date,
+    )
+    .await?;
     run_simulation(
         &mut charging_station,
         solar_system,
         battery_storage,
-        lamb_vec[0].to_vec(),
+        config.lambdas[0].to_vec(),
         "",
         start_date,
         end_date,
         db,
         "norwalk-arts-center",
+        &config.run_config(0, config.strategies[0].as_ref(), &scenario),
     )
     .await?;
 
     let mut i = 1;
-    for battery in battery_param_vec.iter() {
-        for pv in pv_param_vec.iter() {
-            for ev_charger in ev_charger_param_vec.iter() {
-                for rates in lamb_vec.iter() {
-                    let solar_system: PvSystem = PvSystem::new(
-                        (pv.0 * pv.1) as f32,
-                        pv.2,
-                        0.64,
-                        7.0,
-                        7.0,
-                        "full_irradiance_data.csv",
-                    );
-                    let mut battery_storage =
-                        BatteryStorage::new(battery.0, battery.1, 80.0, 48.0, 90.0);
-                    let mut charging_station: Vec<Charger> =
-                        vec![template_charger_180kw.clone(); *ev_charger];
-                    let _ = create_stat_csv("specification_neg_stat.csv");
-                    run_simulation(
-                        &mut charging_station,
-                        solar_system,
-                        &mut battery_storage,
-                        rates.to_vec(),
-                        "",
-                        start_date,
-                        end_date,
-                        db,
-                        "norwalk-art-complex",
-                    )
-                    .await?;
-                    i += 1;
-                    println!("{}", i);
+    for battery in config.battery_params.iter() {
+        for pv in config.pv_params.iter() {
+            for ev_charger in config.fleets.iter() {
+                for rates in config.lambdas.iter() {
+                    // Strategies in a cell are compared on the same vehicles and seed.
+                    let scenario =
+                        Scenario::sample(&config.station, rates.to_vec(), start_date, end_date)
+                            .await?;
+                    for strategy in config.strategies.iter() {
+                        let mut solar_system = base_pv.clone();
+                        solar_system.num_panels = (pv.0 * pv.1) as f32;
+                        solar_system.panel_watts = pv.2;
+                        // The inverter keeps the base system's DC/AC ratio.
+                        if let (Some(inverter), Some(dc_ac_ratio)) =
+                            (&base_pv.inverter, base_pv.dc_ac_ratio())
+                        {
+                            let ac_rating_w = solar_system.dc_rating_w() / dc_ac_ratio;
+                            solar_system.inverter =
+                                Some(inverter.clone().with_ac_rating(ac_rating_w));
+                        }
+                        // Trackers turn the base array, so they need its site.
+                        solar_system.geometry = match (&base_pv.geometry, pv.3) {
+                            (Some(geometry), mounting) => {
+                                Some(geometry.clone().with_mounting(mounting))
+                            }
+                            (None, Mounting::Fixed) => None,
+                            (None, mounting) => {
+                                return Err(format!(
+                                    "{} PV needs an array geometry on the base system",
+                                    mounting.label()
+                                )
+                                .into())
+                            }
+                        };
+                        // battery.0 is the power rating in watts and battery.1 the hours of
+                        // storage at that rating.
+                        let mut battery_storage = base_battery.clone();
+                        battery_storage.capacity = battery.0;
+                        battery_storage.watt_hours = battery.1;
+                        let mut battery_storage =
+                            battery_storage.with_power_limits(battery.0, battery.0);
+                        let mut charging_station: Vec<Charger> =
+                            charger_catalog.build_fleet(ev_charger)?;
+                        let _ = create_stat_csv("specification_neg_stat.csv");
+                        run_simulation(
+                            &mut charging_station,
+                            solar_system,
+                            &mut battery_storage,
+                            rates.to_vec(),
+                            "",
+                            start_date,
+                            end_date,
+                            db,
+                            "norwalk-art-complex",
+                            &config.run_config(i, strategy.as_ref(), &scenario),
+                        )
+                        .await?;
+                        i += 1;
+                        println!("{}", i);
+                    }
                 }
             }
         }
@@ -1,8 +1,26 @@
-use crate::ev_chargers::Charger;
+use crate::ev_chargers::{ChargerCatalog, Connector, FleetMix};
 use crate::photovoltaic::pv_base_system::PvSystem;
+use crate::photovoltaic::solar_geometry::Mounting;
+use battery_spec_test::energy_components::batteries::degradation::DegradationModel;
 use battery_spec_test::energy_components::batteries::BatteryStorage;
+use battery_spec_test::energy_components::charging_curves::ChargingCurve;
+use battery_spec_test::energy_components::dispatch::{
+    DispatchStrategy, PeakShaving, ReserveHolding, SelfConsumption, TimeOfUseArbitrage,
+};
+use battery_spec_test::energy_components::ev_sessions::{
+    QueuePolicy, StationConfig, VehicleProfile,
+};
+use battery_spec_test::energy_components::grid::GridConnection;
+use battery_spec_test::energy_components::load_management::{
+    AllocationPolicy, LoadManager, SiteLimit,
+};
+use battery_spec_test::energy_components::smart_charging::{SmartCharging, SmartChargingObjective};
+use battery_spec_test::energy_components::v2g::V2gControl;
 use battery_spec_test::energy_components::*;
-use battery_spec_test::setup_and_run_simulation;
+use battery_spec_test::finance::FinancialParams;
+use battery_spec_test::tariffs::Tariff;
+use battery_spec_test::{setup_and_run_simulation, SimulationConfig};
+use std::sync::Arc;
 use surrealdb::engine::remote::ws::Ws;
 use surrealdb::opt::auth::Root;
 use surrealdb::Surreal;
@@ -23,29 +41,50 @@ async fn main() {
     db.use_ns("charging-station").await.unwrap();
     db.use_db("batteries").await.unwrap();
 
-    let mut template_charger_180kw = Charger::new(
-        180000.0, 150.0, 1000.0, 600.0, 480.0, 60, 240.0, 300, 199.3, 0.98, 0.94,
-    );
-    let solar_system: PvSystem =
-        PvSystem::new(13000.0, 450.0, 0.64, 7.0, 7.0, "full_irradiance_data.csv");
-    let mut battery_storage = BatteryStorage::new(2000000.0, 4.0, 80.0, 48.0, 90.0);
-    let start_date = "2024-01-01 00:00:00+0000";
-    let end_date = "2024-03-30 00:00:00+0000";
-
-    let battery_param_vec: Vec<(f32, f32)> = vec![
+    let charger_catalog = ChargerCatalog::from_file("chargers.json").unwrap();
+    // Transformer efficiency and panel size are real values on purpose: the efficiency is
+    // applied to every hour of PV, and 1.134 x 1.762 m is the footprint of a 450 W module.
+    // full_irradiance_data.csv has no temp_air, so the cells stay at 25 °C. With weather
+    // columns, add .with_temperature_model(CellTemperatureModel::faiman()).unwrap().
+    let solar_system: PvSystem = PvSystem::new(
+        13000.0,
+        450.0,
+        0.985,
+        1.134,
+        1.762,
+        "full_irradiance_data.csv",
+    )
+    .with_panel_cost(180.0)
+    .with_dc_ac_ratio(1.3);
+    let mut battery_storage = BatteryStorage::new(2000000.0, 4.0, 80.0, 48.0, 90.0)
+        .with_power_limits(2000000.0, 2000000.0)
+        .with_degradation(DegradationModel::lithium_iron_phosphate());
+    let battery_params: Vec<(f32, f32)> = vec![
         (2500000.0, 4.0),
         (3000000.0, 4.0),
         (3500000.0, 4.0),
         (3500000.0, 4.0),
     ];
-    let pv_param_vec: Vec<(usize, usize, f32)> = vec![
-        (22, 500, 450.0),
-        (24, 500, 450.0),
-        (28, 500, 450.0),
-        (32, 500, 450.0),
+    // Tracker rows such as (24, 500, 450.0, Mounting::single_axis(60.0)) or
+    // Mounting::DualAxis need an ArrayGeometry on the base system.
+    let pv_params: Vec<(usize, usize, f32, Mounting)> = vec![
+        (22, 500, 450.0, Mounting::Fixed),
+        (24, 500, 450.0, Mounting::Fixed),
+        (28, 500, 450.0, Mounting::Fixed),
+        (32, 500, 450.0, Mounting::Fixed),
+    ];
+    // Fleet mixes as counts per catalog model.
+    let fleets: Vec<FleetMix> = vec![
+        vec![("dcfc-180kw".to_string(), 20)],
+        vec![
+            ("dcfc-350kw".to_string(), 4),
+            ("dcfc-150kw".to_string(), 8),
+            ("level2-19kw".to_string(), 6),
+            ("dc-bidirectional-25kw".to_string(), 2),
+        ],
     ];
-    let ev_charger_param_vec: Vec<usize> = vec![20];
-    let lamb_vec: Vec<Lambdas> = vec![
+    // Expected vehicle arrivals in each hour of the day.
+    let lambdas: Vec<Lambdas> = vec![
         vec![
             1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 45.0, 31.0, 31.0, 1.0, 45.0, 45.0, 1.0, 45.0, 1.0,
             1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0,
@@ -56,17 +95,127 @@ async fn main() {
         ],
     ];
 
+    // Hours in the tariff are on the simulation clock, local standard time stamped UTC.
+    let tariff = Tariff::from_file("tariff.json").unwrap();
+    let strategies: Vec<Box<dyn DispatchStrategy>> = vec![
+        Box::new(SelfConsumption),
+        Box::new(PeakShaving {
+            import_cap_w: 1500000.0,
+        }),
+        Box::new(TimeOfUseArbitrage {
+            charge_hours: vec![0, 1, 2, 3, 4, 5],
+            // The simulated period falls in the tariff's winter season.
+            discharge_hours: tariff.peak_hours(1),
+            grid_charge_power_w: 1000000.0,
+        }),
+        Box::new(ReserveHolding {
+            reserve_percent: 20.0,
+        }),
+    ];
+
+    let grid = GridConnection::new(2500000.0, 1000000.0, 3000.0).with_tariff(tariff);
+
+    let finance = FinancialParams {
+        battery_cost_per_kwh: 350.0,
+        om_percent_of_capex: 1.5,
+        charging_price_per_kwh: 0.45,
+        discount_rate: 7.0,
+        project_life_years: 20,
+    };
+
+    let vehicle_profiles = vec![
+        VehicleProfile {
+            name: "sedan".to_string(),
+            weight: 0.6,
+            battery_capacity_wh: 75000.0,
+            max_acceptance_w: 150000.0,
+            charging_curve: Some(ChargingCurve::generic_400v()),
+            arrival_soc: (0.1, 0.4),
+            target_soc: 0.8,
+            dwell_minutes: (20, 45),
+            connectors: vec![Connector::Ccs1, Connector::J1772],
+            v2g_capable: false,
+        },
+        VehicleProfile {
+            name: "suv".to_string(),
+            weight: 0.3,
+            battery_capacity_wh: 100000.0,
+            max_acceptance_w: 250000.0,
+            charging_curve: Some(ChargingCurve::generic_800v()),
+            arrival_soc: (0.1, 0.4),
+            target_soc: 0.8,
+            dwell_minutes: (20, 45),
+            connectors: vec![Connector::Ccs1, Connector::Nacs, Connector::J1772],
+            v2g_capable: false,
+        },
+        VehicleProfile {
+            name: "light-truck".to_string(),
+            weight: 0.1,
+            battery_capacity_wh: 130000.0,
+            max_acceptance_w: 150000.0,
+            charging_curve: Some(ChargingCurve::generic_400v()),
+            arrival_soc: (0.1, 0.3),
+            target_soc: 0.9,
+            dwell_minutes: (30, 60),
+            connectors: vec![Connector::Ccs1, Connector::J1772],
+            v2g_capable: false,
+        },
+        // Depot vans parked for hours with charge to spare, available for V2G.
+        VehicleProfile {
+            name: "fleet-van".to_string(),
+            weight: 0.05,
+            battery_capacity_wh: 90000.0,
+            max_acceptance_w: 100000.0,
+            charging_curve: Some(ChargingCurve::generic_400v()),
+            arrival_soc: (0.6, 0.9),
+            target_soc: 0.5,
+            dwell_minutes: (240, 600),
+            connectors: vec![Connector::Ccs1],
+            v2g_capable: true,
+        },
+    ];
+    // Drivers leave when five cars are already waiting and give up after 15 minutes on average.
+    let station = StationConfig {
+        vehicle_profiles,
+        queue_policy: QueuePolicy {
+            max_queue_length: Some(5),
+            mean_patience_minutes: Some(15.0),
+        },
+        // The service is sized below every charger running flat out at once.
+        load_manager: Some(LoadManager::new(
+            SiteLimit::Static(1500000.0),
+            AllocationPolicy::EqualShare,
+        )),
+        // Connected vans discharge to keep station import under 1 MW.
+        v2g: Some(V2gControl::new(Arc::new(PeakShaving {
+            import_cap_w: 1000000.0,
+        }))),
+        // Sessions staying two hours or more are shifted toward PV hours.
+        smart_charging: Some(SmartCharging::new(
+            SmartChargingObjective::PvSelfConsumption,
+            120,
+        )),
+        // Public arrivals; set to FleetSchedule::from_csv("fleet_schedule.csv") for a depot run.
+        fleet_schedule: None,
+    };
+
     setup_and_run_simulation(
         &db,
-        &mut template_charger_180kw,
+        &charger_catalog,
         solar_system,
         &mut battery_storage,
-        start_date,
-        end_date,
-        battery_param_vec,
-        pv_param_vec,
-        ev_charger_param_vec,
-        lamb_vec,
+        SimulationConfig {
+            start_date: "2024-01-01 00:00:00+0000".to_string(),
+            end_date: "2024-03-30 00:00:00+0000".to_string(),
+            battery_params,
+            pv_params,
+            fleets,
+            lambdas,
+            strategies,
+            grid,
+            finance,
+            station,
+        },
     )
     .await
     .unwrap();
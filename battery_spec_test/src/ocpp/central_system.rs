@@ -0,0 +1,292 @@
+use crate::ocpp::messages::{
+    BootNotification, Frame, MeterValues, StartTransaction, StatusNotification, StopTransaction,
+    ENERGY_REGISTER, SUBPROTOCOL,
+};
+use anyhow::Result;
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use futures_util::{SinkExt, StreamExt};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Message;
+
+/// One reading from a MeterValues request. `value` is in watts or watt hours by measurand.
+#[derive(Clone, Debug)]
+pub struct MeterSample {
+    pub charge_point_id: String,
+    pub connector_id: u32,
+    pub transaction_id: Option<i32>,
+    pub timestamp: DateTime<Utc>,
+    pub measurand: String,
+    pub value: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct TransactionRecord {
+    pub charge_point_id: String,
+    pub transaction_id: i32,
+    pub connector_id: u32,
+    pub id_tag: String,
+    pub start: DateTime<Utc>,
+    pub meter_start_wh: f32,
+    pub stop: Option<DateTime<Utc>>,
+    pub meter_stop_wh: Option<f32>,
+}
+
+/// Everything the central system has been told, in arrival order.
+#[derive(Clone, Debug, Default)]
+pub struct MeterLog {
+    /// Charge point id and model from each BootNotification.
+    pub boots: Vec<(String, String)>,
+    pub transactions: Vec<TransactionRecord>,
+    pub samples: Vec<MeterSample>,
+}
+
+impl MeterLog {
+    /// Hourly demand in watts from the energy registers of every transaction, from the hour of
+    /// the first reading to the hour of the last. Energy between two readings is spread over
+    /// the hours between them in proportion to time.
+    pub fn demand_w_ts(&self) -> Vec<(DateTime<Utc>, f32)> {
+        let mut readings: Vec<Vec<(DateTime<Utc>, f32)>> = self
+            .transactions
+            .iter()
+            .map(|transaction| {
+                let mut points = vec![(transaction.start, transaction.meter_start_wh)];
+                points.extend(
+                    self.samples
+                        .iter()
+                        .filter(|sample| {
+                            sample.charge_point_id == transaction.charge_point_id
+                                && sample.transaction_id == Some(transaction.transaction_id)
+                                && sample.measurand == ENERGY_REGISTER
+                        })
+                        .map(|sample| (sample.timestamp, sample.value)),
+                );
+                if let (Some(stop), Some(meter_stop_wh)) =
+                    (transaction.stop, transaction.meter_stop_wh)
+                {
+                    points.push((stop, meter_stop_wh));
+                }
+                points.sort_by_key(|(timestamp, _)| *timestamp);
+                points
+            })
+            .collect();
+        readings.retain(|points| points.len() > 1);
+        let hour = TimeDelta::try_hours(1).unwrap();
+        let (Some(first), Some(last)) = (
+            readings.iter().map(|points| points[0].0).min(),
+            readings
+                .iter()
+                .map(|points| points[points.len() - 1].0)
+                .max(),
+        ) else {
+            return Vec::new();
+        };
+        let first_hour = first.duration_trunc(hour).unwrap();
+        let hours = ((last - first_hour).num_seconds() / 3600 + 1) as usize;
+        let mut demand_wh = vec![0.0; hours];
+        for points in readings.iter() {
+            for pair in points.windows(2) {
+                let ((from, from_wh), (to, to_wh)) = (pair[0], pair[1]);
+                let seconds = (to - from).num_seconds() as f32;
+                if seconds <= 0.0 {
+                    continue;
+                }
+                let mut cursor = from;
+                while cursor < to {
+                    let hour_start = cursor.duration_trunc(hour).unwrap();
+                    let until = (hour_start + hour).min(to);
+                    let idx = ((hour_start - first_hour).num_seconds() / 3600) as usize;
+                    demand_wh[idx] +=
+                        (to_wh - from_wh) * (until - cursor).num_seconds() as f32 / seconds;
+                    cursor = until;
+                }
+            }
+        }
+        demand_wh
+            .into_iter()
+            .enumerate()
+            .map(|(idx, wh)| (first_hour + hour * idx as i32, wh))
+            .collect()
+    }
+}
+
+/// A local OCPP 1.6J central system. Charge points connect to `ws://<addr>/<charge point id>`;
+/// it accepts every boot, id tag and transaction and records what it is sent.
+pub struct CentralSystem {
+    listener: TcpListener,
+    log: Arc<Mutex<MeterLog>>,
+}
+
+impl CentralSystem {
+    pub async fn bind(addr: &str) -> Result<CentralSystem> {
+        Ok(CentralSystem {
+            listener: TcpListener::bind(addr).await?,
+            log: Arc::new(Mutex::new(MeterLog::default())),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Shared handle to what has been recorded so far.
+    pub fn log(&self) -> Arc<Mutex<MeterLog>> {
+        self.log.clone()
+    }
+
+    /// Accepts charge points until the task is dropped, each on its own task.
+    pub async fn serve(self) -> Result<()> {
+        loop {
+            let (stream, _) = self.listener.accept().await?;
+            let log = self.log.clone();
+            tokio::spawn(async move {
+                if let Err(err) = handle_connection(stream, log).await {
+                    println!("charge point connection closed: {}", err);
+                }
+            });
+        }
+    }
+}
+
+// The handshake callback's error type is tungstenite's HTTP response.
+#[allow(clippy::result_large_err)]
+async fn handle_connection(stream: TcpStream, log: Arc<Mutex<MeterLog>>) -> Result<()> {
+    let mut charge_point_id = String::new();
+    let mut ws =
+        tokio_tungstenite::accept_hdr_async(stream, |request: &Request, mut response: Response| {
+            charge_point_id = request
+                .uri()
+                .path()
+                .rsplit('/')
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            let offers_ocpp16 = request
+                .headers()
+                .get_all("Sec-WebSocket-Protocol")
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .any(|value| {
+                    value
+                        .split(',')
+                        .any(|protocol| protocol.trim() == SUBPROTOCOL)
+                });
+            if offers_ocpp16 {
+                response.headers_mut().insert(
+                    "Sec-WebSocket-Protocol",
+                    HeaderValue::from_static(SUBPROTOCOL),
+                );
+            }
+            Ok(response)
+        })
+        .await?;
+    while let Some(message) = ws.next().await {
+        let text = match message? {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let Ok(Frame::Call {
+            unique_id,
+            action,
+            payload,
+        }) = Frame::parse(&text)
+        else {
+            continue;
+        };
+        let result = handle_call(&charge_point_id, &action, payload, &mut log.lock().unwrap());
+        let reply = match result {
+            Ok(payload) => Frame::CallResult { unique_id, payload },
+            Err((error_code, error_description)) => Frame::CallError {
+                unique_id,
+                error_code: error_code.to_string(),
+                error_description,
+            },
+        };
+        ws.send(Message::Text(reply.to_text())).await?;
+    }
+    Ok(())
+}
+
+fn payload<T: DeserializeOwned>(payload: Value) -> Result<T, (&'static str, String)> {
+    serde_json::from_value(payload).map_err(|err| ("FormationViolation", err.to_string()))
+}
+
+/// Result payload for one request, or an OCPP error code and description.
+fn handle_call(
+    charge_point_id: &str,
+    action: &str,
+    request: Value,
+    log: &mut MeterLog,
+) -> Result<Value, (&'static str, String)> {
+    let accepted = json!({ "status": "Accepted" });
+    match action {
+        "BootNotification" => {
+            let boot: BootNotification = payload(request)?;
+            log.boots
+                .push((charge_point_id.to_string(), boot.charge_point_model));
+            Ok(json!({
+                "status": "Accepted",
+                "currentTime": Utc::now(),
+                "interval": 300,
+            }))
+        }
+        "Heartbeat" => Ok(json!({ "currentTime": Utc::now() })),
+        "StatusNotification" => {
+            let _: StatusNotification = payload(request)?;
+            Ok(json!({}))
+        }
+        "Authorize" => Ok(json!({ "idTagInfo": accepted })),
+        "StartTransaction" => {
+            let start: StartTransaction = payload(request)?;
+            let transaction_id = log.transactions.len() as i32 + 1;
+            log.transactions.push(TransactionRecord {
+                charge_point_id: charge_point_id.to_string(),
+                transaction_id,
+                connector_id: start.connector_id,
+                id_tag: start.id_tag,
+                start: start.timestamp,
+                meter_start_wh: start.meter_start as f32,
+                stop: None,
+                meter_stop_wh: None,
+            });
+            Ok(json!({ "transactionId": transaction_id, "idTagInfo": accepted }))
+        }
+        "MeterValues" => {
+            let meter_values: MeterValues = payload(request)?;
+            for meter_value in meter_values.meter_value {
+                for sampled in meter_value.sampled_value {
+                    let value = sampled
+                        .value_in_base_unit()
+                        .map_err(|err| ("TypeConstraintViolation", err.to_string()))?;
+                    log.samples.push(MeterSample {
+                        charge_point_id: charge_point_id.to_string(),
+                        connector_id: meter_values.connector_id,
+                        transaction_id: meter_values.transaction_id,
+                        timestamp: meter_value.timestamp,
+                        measurand: sampled.measurand().to_string(),
+                        value,
+                    });
+                }
+            }
+            Ok(json!({}))
+        }
+        "StopTransaction" => {
+            let stop: StopTransaction = payload(request)?;
+            if let Some(transaction) = log.transactions.iter_mut().find(|transaction| {
+                transaction.charge_point_id == charge_point_id
+                    && transaction.transaction_id == stop.transaction_id
+            }) {
+                transaction.stop = Some(stop.timestamp);
+                transaction.meter_stop_wh = Some(stop.meter_stop as f32);
+            }
+            Ok(json!({ "idTagInfo": accepted }))
+        }
+        _ => Err(("NotImplemented", format!("{} is not supported", action))),
+    }
+}
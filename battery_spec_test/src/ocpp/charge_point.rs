@@ -0,0 +1,303 @@
+use crate::energy_components::ev_chargers::Charger;
+use crate::energy_components::ev_sessions::{EvSession, SessionStatus};
+use crate::ocpp::messages::{
+    BootNotification, Frame, MeterValue, MeterValues, SampledValue, StartTransaction,
+    StatusNotification, StopTransaction, ACTIVE_POWER, ACTIVE_POWER_EXPORT, ENERGY_REGISTER,
+    SUBPROTOCOL,
+};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use futures_util::{SinkExt, StreamExt};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+/// One session as the charge point will report it.
+#[derive(Clone, Debug)]
+struct ReplayTransaction {
+    id_tag: String,
+    start: DateTime<Utc>,
+    stop: DateTime<Utc>,
+    reason: &'static str,
+    /// Energy register when the session starts, in watt hours.
+    meter_start_wh: f32,
+    /// AC energy the session drew, in watt hours.
+    energy_wh: f32,
+}
+
+/// Replays one simulated charger's sessions as an OCPP 1.6J charge point with a single
+/// connector. Timestamps are the simulated ones and messages go out as fast as the central
+/// system answers. The meter counts the AC input of sessions only; standby is left out and
+/// V2G export is sampled as its own measurand.
+#[derive(Clone, Debug)]
+pub struct ChargePointSimulator {
+    pub charge_point_id: String,
+    pub model: String,
+    /// Hourly AC input of the charger in watts, from the simulation.
+    input_w_ts: Vec<(DateTime<Utc>, f32)>,
+    /// Hourly V2G export of the charger in watts.
+    export_w_ts: Vec<(DateTime<Utc>, f32)>,
+    transactions: Vec<ReplayTransaction>,
+}
+
+impl ChargePointSimulator {
+    /// Builds the replay for charger `charger_index` of a finished run.
+    pub fn from_run(
+        charge_point_id: &str,
+        charger: &Charger,
+        charger_index: usize,
+        sessions: &[EvSession],
+    ) -> Self {
+        let mut transactions: Vec<ReplayTransaction> = sessions
+            .iter()
+            .filter(|session| session.charger_index == Some(charger_index))
+            .filter_map(|session| {
+                Some(ReplayTransaction {
+                    id_tag: format!("vehicle-{}", session.vehicle_id),
+                    start: session.plug_in?,
+                    stop: session.departure?,
+                    reason: match session.status {
+                        SessionStatus::Interrupted => "PowerLoss",
+                        _ => "EVDisconnected",
+                    },
+                    meter_start_wh: 0.0,
+                    energy_wh: session.energy_delivered_wh / charger.efficiency_at_nominal_power,
+                })
+            })
+            .collect();
+        transactions.sort_by_key(|transaction| transaction.start);
+        let mut register_wh = 0.0;
+        for transaction in transactions.iter_mut() {
+            transaction.meter_start_wh = register_wh;
+            register_wh += transaction.energy_wh;
+        }
+        ChargePointSimulator {
+            charge_point_id: charge_point_id.to_string(),
+            model: charger.model.clone(),
+            input_w_ts: charger.power.input_power_w_ts.clone().unwrap_or_default(),
+            export_w_ts: charger.power.export_w_ts.clone().unwrap_or_default(),
+            transactions,
+        }
+    }
+
+    /// Charger input between two times in watt hours, taking each hour as drawn evenly.
+    fn input_wh(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> f32 {
+        let hour = TimeDelta::try_hours(1).unwrap();
+        self.input_w_ts
+            .iter()
+            .map(|&(start, w)| {
+                let seconds = ((start + hour).min(to) - start.max(from)).num_seconds();
+                w * seconds.max(0) as f32 / 3600.0
+            })
+            .sum()
+    }
+
+    /// Energy register in watt hours at `date` during `transaction`. The session's energy is
+    /// spread over its span in proportion to the charger's hourly input.
+    fn register_wh(&self, transaction: &ReplayTransaction, date: DateTime<Utc>) -> f32 {
+        let total_wh = self.input_wh(transaction.start, transaction.stop);
+        let share = if total_wh > 0.0 {
+            self.input_wh(transaction.start, date) / total_wh
+        } else {
+            (date - transaction.start).num_seconds() as f32
+                / (transaction.stop - transaction.start).num_seconds().max(1) as f32
+        };
+        transaction.meter_start_wh + transaction.energy_wh * share.clamp(0.0, 1.0)
+    }
+
+    fn power_w(series: &[(DateTime<Utc>, f32)], date: DateTime<Utc>) -> f32 {
+        let idx = series.partition_point(|(hour, _)| *hour <= date);
+        series.get(idx.wrapping_sub(1)).map_or(0.0, |(_, w)| *w)
+    }
+
+    /// Readings for one hourly MeterValues, with export only while the charger exports.
+    fn sampled_values(
+        &self,
+        transaction: &ReplayTransaction,
+        date: DateTime<Utc>,
+    ) -> Vec<SampledValue> {
+        let mut sampled_value = vec![
+            SampledValue {
+                value: format!("{:.0}", self.register_wh(transaction, date)),
+                measurand: Some(ENERGY_REGISTER.to_string()),
+                unit: Some("Wh".to_string()),
+            },
+            SampledValue {
+                value: format!("{:.0}", Self::power_w(&self.input_w_ts, date)),
+                measurand: Some(ACTIVE_POWER.to_string()),
+                unit: Some("W".to_string()),
+            },
+        ];
+        let export_w = Self::power_w(&self.export_w_ts, date);
+        if export_w > 0.0 {
+            sampled_value.push(SampledValue {
+                value: format!("{:.0}", export_w),
+                measurand: Some(ACTIVE_POWER_EXPORT.to_string()),
+                unit: Some("W".to_string()),
+            });
+        }
+        sampled_value
+    }
+
+    /// Connects to `central_system_url` as this charge point and replays every transaction:
+    /// BootNotification, then per session StatusNotification, StartTransaction, hourly
+    /// MeterValues and StopTransaction.
+    pub async fn run(&self, central_system_url: &str) -> Result<()> {
+        let url = format!(
+            "{}/{}",
+            central_system_url.trim_end_matches('/'),
+            self.charge_point_id
+        );
+        let mut connection = Connection::open(&url).await?;
+        connection
+            .call(
+                "BootNotification",
+                BootNotification {
+                    charge_point_vendor: "battery_spec_test".to_string(),
+                    charge_point_model: self.model.clone(),
+                },
+            )
+            .await?;
+        let first = self
+            .transactions
+            .first()
+            .map_or_else(Utc::now, |transaction| transaction.start);
+        connection.status(first, "Available").await?;
+        let hour = TimeDelta::try_hours(1).unwrap();
+        for transaction in self.transactions.iter() {
+            connection.status(transaction.start, "Preparing").await?;
+            let response = connection
+                .call(
+                    "StartTransaction",
+                    StartTransaction {
+                        connector_id: 1,
+                        id_tag: transaction.id_tag.clone(),
+                        meter_start: transaction.meter_start_wh.round() as i64,
+                        timestamp: transaction.start,
+                    },
+                )
+                .await?;
+            let transaction_id = response["transactionId"]
+                .as_i64()
+                .ok_or_else(|| anyhow!("StartTransaction reply has no transactionId"))?
+                as i32;
+            connection.status(transaction.start, "Charging").await?;
+            let mut sample_at = transaction.start.duration_trunc(hour)? + hour;
+            while sample_at < transaction.stop {
+                connection
+                    .call(
+                        "MeterValues",
+                        MeterValues {
+                            connector_id: 1,
+                            transaction_id: Some(transaction_id),
+                            meter_value: vec![MeterValue {
+                                timestamp: sample_at,
+                                sampled_value: self.sampled_values(transaction, sample_at),
+                            }],
+                        },
+                    )
+                    .await?;
+                sample_at += hour;
+            }
+            connection
+                .call(
+                    "StopTransaction",
+                    StopTransaction {
+                        transaction_id,
+                        meter_stop: (transaction.meter_start_wh + transaction.energy_wh).round()
+                            as i64,
+                        timestamp: transaction.stop,
+                        reason: Some(transaction.reason.to_string()),
+                    },
+                )
+                .await?;
+            connection.status(transaction.stop, "Available").await?;
+        }
+        connection.close().await
+    }
+}
+
+/// Client end of the websocket, sending one request at a time.
+struct Connection {
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    next_id: u64,
+}
+
+impl Connection {
+    async fn open(url: &str) -> Result<Connection> {
+        let mut request = url.into_client_request()?;
+        request.headers_mut().insert(
+            "Sec-WebSocket-Protocol",
+            HeaderValue::from_static(SUBPROTOCOL),
+        );
+        let (ws, _) = tokio_tungstenite::connect_async(request).await?;
+        Ok(Connection { ws, next_id: 1 })
+    }
+
+    /// Sends a request and waits for its result payload.
+    async fn call<T: Serialize>(&mut self, action: &str, payload: T) -> Result<Value> {
+        let unique_id = self.next_id.to_string();
+        self.next_id += 1;
+        let call = Frame::Call {
+            unique_id: unique_id.clone(),
+            action: action.to_string(),
+            payload: serde_json::to_value(payload)?,
+        };
+        self.ws.send(Message::Text(call.to_text())).await?;
+        while let Some(message) = self.ws.next().await {
+            let Message::Text(text) = message? else {
+                continue;
+            };
+            match Frame::parse(&text)? {
+                Frame::CallResult {
+                    unique_id: reply_id,
+                    payload,
+                } if reply_id == unique_id => return Ok(payload),
+                Frame::CallError {
+                    unique_id: reply_id,
+                    error_code,
+                    error_description,
+                } if reply_id == unique_id => {
+                    bail!("{} rejected: {} {}", action, error_code, error_description)
+                }
+                // Requests from the central system are not supported by the replay.
+                Frame::Call {
+                    unique_id: request_id,
+                    ..
+                } => {
+                    let reply = Frame::CallError {
+                        unique_id: request_id,
+                        error_code: "NotImplemented".to_string(),
+                        error_description: String::new(),
+                    };
+                    self.ws.send(Message::Text(reply.to_text())).await?;
+                }
+                _ => {}
+            }
+        }
+        bail!("central system closed the connection during {}", action)
+    }
+
+    async fn status(&mut self, timestamp: DateTime<Utc>, status: &str) -> Result<()> {
+        self.call(
+            "StatusNotification",
+            StatusNotification {
+                connector_id: 1,
+                error_code: "NoError".to_string(),
+                status: status.to_string(),
+                timestamp: Some(timestamp),
+            },
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn close(mut self) -> Result<()> {
+        self.ws.close(None).await?;
+        Ok(())
+    }
+}
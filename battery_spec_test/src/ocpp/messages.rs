@@ -0,0 +1,165 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Websocket subprotocol both ends must agree on.
+pub const SUBPROTOCOL: &str = "ocpp1.6";
+
+pub const ENERGY_REGISTER: &str = "Energy.Active.Import.Register";
+pub const ACTIVE_POWER: &str = "Power.Active.Import";
+pub const ACTIVE_POWER_EXPORT: &str = "Power.Active.Export";
+
+/// One OCPP-J message: a request, its result, or an error in reply to it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Frame {
+    Call {
+        unique_id: String,
+        action: String,
+        payload: Value,
+    },
+    CallResult {
+        unique_id: String,
+        payload: Value,
+    },
+    CallError {
+        unique_id: String,
+        error_code: String,
+        error_description: String,
+    },
+}
+
+impl Frame {
+    pub fn parse(text: &str) -> Result<Frame> {
+        let Value::Array(parts) = serde_json::from_str(text)? else {
+            bail!("OCPP message is not an array");
+        };
+        let unique_id = parts
+            .get(1)
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("OCPP message has no unique id"))?
+            .to_string();
+        let text_at = |idx: usize| {
+            parts
+                .get(idx)
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string()
+        };
+        match parts.first().and_then(Value::as_u64) {
+            Some(2) => Ok(Frame::Call {
+                unique_id,
+                action: text_at(2),
+                payload: parts.get(3).cloned().unwrap_or(Value::Null),
+            }),
+            Some(3) => Ok(Frame::CallResult {
+                unique_id,
+                payload: parts.get(2).cloned().unwrap_or(Value::Null),
+            }),
+            Some(4) => Ok(Frame::CallError {
+                unique_id,
+                error_code: text_at(2),
+                error_description: text_at(3),
+            }),
+            _ => bail!("unknown OCPP message type"),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        match self {
+            Frame::Call {
+                unique_id,
+                action,
+                payload,
+            } => json!([2, unique_id, action, payload]),
+            Frame::CallResult { unique_id, payload } => json!([3, unique_id, payload]),
+            Frame::CallError {
+                unique_id,
+                error_code,
+                error_description,
+            } => json!([4, unique_id, error_code, error_description, {}]),
+        }
+        .to_string()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BootNotification {
+    pub charge_point_vendor: String,
+    pub charge_point_model: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusNotification {
+    pub connector_id: u32,
+    pub error_code: String,
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartTransaction {
+    pub connector_id: u32,
+    pub id_tag: String,
+    /// Energy register at the start, in watt hours.
+    pub meter_start: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StopTransaction {
+    pub transaction_id: i32,
+    /// Energy register at the end, in watt hours.
+    pub meter_stop: i64,
+    pub timestamp: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MeterValues {
+    pub connector_id: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction_id: Option<i32>,
+    pub meter_value: Vec<MeterValue>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MeterValue {
+    pub timestamp: DateTime<Utc>,
+    pub sampled_value: Vec<SampledValue>,
+}
+
+/// A reading as OCPP sends it, with the number as a decimal string. Without a measurand it is
+/// the energy register.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SampledValue {
+    pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub measurand: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+}
+
+impl SampledValue {
+    /// Reading in watts or watt hours, scaling kW and kWh readings.
+    pub fn value_in_base_unit(&self) -> Result<f32> {
+        let value: f32 = self.value.parse()?;
+        Ok(match self.unit.as_deref() {
+            Some("kW") | Some("kWh") => value * 1000.0,
+            _ => value,
+        })
+    }
+
+    pub fn measurand(&self) -> &str {
+        self.measurand.as_deref().unwrap_or(ENERGY_REGISTER)
+    }
+}
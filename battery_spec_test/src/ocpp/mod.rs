@@ -0,0 +1,3 @@
+pub mod central_system;
+pub mod charge_point;
+pub mod messages;
@@ -0,0 +1,271 @@
+use chrono::{DateTime, Datelike, TimeDelta, Timelike, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// A utility tariff read from a JSON definition. Energy is priced per kWh by season and
+/// time-of-use period, demand is charged per kW of the month's highest hourly import, and
+/// exported energy is credited at a flat rate.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Tariff {
+    pub name: String,
+    /// Offset from UTC of the local clock the periods are defined in. Zero when the series
+    /// timestamps are already on that clock, as with the bundled irradiance data.
+    #[serde(default)]
+    pub utc_offset_hours: i32,
+    pub fixed_monthly_fee: f64,
+    pub export_rate_per_kwh: f64,
+    pub seasons: Vec<Season>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Season {
+    pub name: String,
+    /// Calendar months (1-12) the season covers.
+    pub months: Vec<u32>,
+    /// Charged on the month's highest import regardless of period.
+    #[serde(default)]
+    pub demand_charge_per_kw: f64,
+    /// Checked in order; the first match prices the hour.
+    pub periods: Vec<TouPeriod>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TouPeriod {
+    pub name: String,
+    /// Local hours of day (0-23) in the period. Empty matches every hour.
+    #[serde(default)]
+    pub hours: Vec<u32>,
+    #[serde(default)]
+    pub weekdays_only: bool,
+    pub energy_rate_per_kwh: f64,
+    /// Charged on the month's highest import within this period.
+    #[serde(default)]
+    pub demand_charge_per_kw: f64,
+}
+
+/// One month of a bill. Energies are in kWh, demands in kW and charges in the tariff currency.
+#[derive(Clone, Debug, Serialize)]
+pub struct MonthlyBill {
+    pub year: i32,
+    pub month: u32,
+    pub import_kwh: f64,
+    pub energy_charge: f64,
+    pub peak_demand_kw: f64,
+    pub demand_charge: f64,
+    pub fixed_charge: f64,
+    pub export_kwh: f64,
+    pub export_credit: f64,
+    pub total: f64,
+}
+
+impl Tariff {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Tariff, anyhow::Error> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    fn season(&self, month: u32) -> Option<&Season> {
+        self.seasons
+            .iter()
+            .find(|season| season.months.contains(&month))
+    }
+
+    fn period(season: &Season, local: DateTime<Utc>) -> Option<(usize, &TouPeriod)> {
+        let weekend = matches!(local.weekday(), Weekday::Sat | Weekday::Sun);
+        season.periods.iter().enumerate().find(|(_, period)| {
+            (period.hours.is_empty() || period.hours.contains(&local.hour()))
+                && !(period.weekdays_only && weekend)
+        })
+    }
+
+    /// Energy rate per kWh in effect at `date`, or zero for hours the tariff does not price.
+    pub fn energy_rate_at(&self, date: DateTime<Utc>) -> f64 {
+        let local = date + TimeDelta::try_hours(self.utc_offset_hours.into()).unwrap();
+        self.season(local.month())
+            .and_then(|season| Self::period(season, local))
+            .map_or(0.0, |(_, period)| period.energy_rate_per_kwh)
+    }
+
+    /// Hours of day on the series clock in the highest-priced period of `month`'s season, or
+    /// none when the month is not covered or the period spans the whole day.
+    pub fn peak_hours(&self, month: u32) -> Vec<u32> {
+        let Some(period) = self.season(month).and_then(|season| {
+            season
+                .periods
+                .iter()
+                .max_by(|a, b| a.energy_rate_per_kwh.total_cmp(&b.energy_rate_per_kwh))
+        }) else {
+            return Vec::new();
+        };
+        period
+            .hours
+            .iter()
+            .map(|hour| (*hour as i32 - self.utc_offset_hours).rem_euclid(24) as u32)
+            .collect()
+    }
+
+    /// Bills hourly grid import and export series, both in watts, month by month. Hours the
+    /// tariff does not price are billed at zero.
+    pub fn monthly_bills(
+        &self,
+        import_w_ts: &[(DateTime<Utc>, f32)],
+        export_w_ts: &[(DateTime<Utc>, f32)],
+    ) -> Vec<MonthlyBill> {
+        let offset = TimeDelta::try_hours(self.utc_offset_hours.into()).unwrap();
+        let mut bills: Vec<MonthlyBill> = Vec::new();
+        // Highest kW imported in each period of the month being billed.
+        let mut period_peaks: Vec<f64> = Vec::new();
+        for (&(date, import_w), &(_, export_w)) in import_w_ts.iter().zip(export_w_ts.iter()) {
+            let local = date + offset;
+            let import_kw = import_w as f64 / 1000.0;
+            let export_kw = export_w as f64 / 1000.0;
+            let new_month = bills
+                .last()
+                .is_none_or(|bill| (bill.year, bill.month) != (local.year(), local.month()));
+            if new_month {
+                if let Some(bill) = bills.last_mut() {
+                    self.close_month(bill, &period_peaks);
+                }
+                period_peaks = vec![0.0; self.season(local.month()).map_or(0, |s| s.periods.len())];
+                bills.push(MonthlyBill {
+                    year: local.year(),
+                    month: local.month(),
+                    import_kwh: 0.0,
+                    energy_charge: 0.0,
+                    peak_demand_kw: 0.0,
+                    demand_charge: 0.0,
+                    fixed_charge: self.fixed_monthly_fee,
+                    export_kwh: 0.0,
+                    export_credit: 0.0,
+                    total: 0.0,
+                });
+            }
+            let bill = bills.last_mut().unwrap();
+            bill.import_kwh += import_kw;
+            bill.export_kwh += export_kw;
+            bill.export_credit += export_kw * self.export_rate_per_kwh;
+            bill.peak_demand_kw = bill.peak_demand_kw.max(import_kw);
+            let period = self
+                .season(local.month())
+                .and_then(|season| Self::period(season, local));
+            if let Some((idx, period)) = period {
+                bill.energy_charge += import_kw * period.energy_rate_per_kwh;
+                period_peaks[idx] = period_peaks[idx].max(import_kw);
+            }
+        }
+        if let Some(bill) = bills.last_mut() {
+            self.close_month(bill, &period_peaks);
+        }
+        bills
+    }
+
+    fn close_month(&self, bill: &mut MonthlyBill, period_peaks: &[f64]) {
+        if let Some(season) = self.season(bill.month) {
+            bill.demand_charge += bill.peak_demand_kw * season.demand_charge_per_kw;
+            for (period, peak_kw) in season.periods.iter().zip(period_peaks) {
+                bill.demand_charge += peak_kw * period.demand_charge_per_kw;
+            }
+        }
+        bill.total =
+            bill.energy_charge + bill.demand_charge + bill.fixed_charge - bill.export_credit;
+    }
+
+    /// Sum of the monthly totals.
+    pub fn total_bill(bills: &[MonthlyBill]) -> f64 {
+        bills.iter().map(|bill| bill.total).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn tariff(utc_offset_hours: i32) -> Tariff {
+        Tariff {
+            name: "test".to_string(),
+            utc_offset_hours,
+            fixed_monthly_fee: 100.0,
+            export_rate_per_kwh: 0.05,
+            seasons: vec![Season {
+                name: "all year".to_string(),
+                months: (1..=12).collect(),
+                demand_charge_per_kw: 10.0,
+                periods: vec![
+                    TouPeriod {
+                        name: "on-peak".to_string(),
+                        hours: vec![16, 17, 18, 19, 20],
+                        weekdays_only: true,
+                        energy_rate_per_kwh: 0.30,
+                        demand_charge_per_kw: 5.0,
+                    },
+                    TouPeriod {
+                        name: "off-peak".to_string(),
+                        hours: Vec::new(),
+                        weekdays_only: false,
+                        energy_rate_per_kwh: 0.10,
+                        demand_charge_per_kw: 0.0,
+                    },
+                ],
+            }],
+        }
+    }
+
+    fn hour(month: u32, day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, month, day, hour, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn bills_a_hand_computed_month() {
+        // (date, import W, export W)
+        let hours = [
+            (hour(6, 3, 10), 2000.0, 0.0), // Monday off-peak: 2 kWh at 0.10
+            (hour(6, 3, 17), 4000.0, 0.0), // Monday on-peak: 4 kWh at 0.30
+            (hour(6, 4, 12), 0.0, 3000.0), // 3 kWh exported at 0.05
+            (hour(6, 8, 17), 6000.0, 0.0), // Saturday, so off-peak: 6 kWh at 0.10
+            (hour(7, 1, 3), 1000.0, 0.0),  // July off-peak: 1 kWh at 0.10
+        ];
+        let import: Vec<(DateTime<Utc>, f32)> = hours
+            .iter()
+            .map(|&(date, import, _)| (date, import))
+            .collect();
+        let export: Vec<(DateTime<Utc>, f32)> = hours
+            .iter()
+            .map(|&(date, _, export)| (date, export))
+            .collect();
+        let bills = tariff(0).monthly_bills(&import, &export);
+        assert_eq!(bills.len(), 2);
+
+        let june = &bills[0];
+        assert_eq!((june.year, june.month), (2024, 6));
+        assert!((june.import_kwh - 12.0).abs() < 1e-9);
+        assert!((june.energy_charge - 2.0).abs() < 1e-9);
+        assert!((june.peak_demand_kw - 6.0).abs() < 1e-9);
+        // 6 kW at 10 over the month plus 4 kW at 5 on-peak.
+        assert!((june.demand_charge - 80.0).abs() < 1e-9);
+        assert!((june.export_credit - 0.15).abs() < 1e-9);
+        assert!((june.total - 181.85).abs() < 1e-9);
+
+        let july = &bills[1];
+        assert_eq!((july.year, july.month), (2024, 7));
+        assert!((july.total - 110.1).abs() < 1e-9);
+        assert!((Tariff::total_bill(&bills) - 291.95).abs() < 1e-9);
+    }
+
+    #[test]
+    fn peak_hours_follow_the_local_clock() {
+        assert_eq!(tariff(0).peak_hours(1), vec![16, 17, 18, 19, 20]);
+        assert_eq!(tariff(-5).peak_hours(1), vec![21, 22, 23, 0, 1]);
+    }
+
+    #[test]
+    fn prices_hours_on_the_local_clock() {
+        // 22:00 UTC is 17:00 on a Monday in UTC-5, so on-peak; 17:00 UTC is off-peak.
+        let import = [(hour(6, 3, 22), 1000.0), (hour(6, 3, 17), 1000.0)];
+        let export = [(hour(6, 3, 22), 0.0), (hour(6, 3, 17), 0.0)];
+        let bills = tariff(-5).monthly_bills(&import, &export);
+        assert!((bills[0].energy_charge - 0.40).abs() < 1e-9);
+    }
+}
@@ -91,3 +91,33 @@ pub async fn modulated_markov_poison_process(
     Ok((data_matrix, parameter_matrix, days_matrix))
 }
 
+/// Samples vehicle arrival times between `date1` and `date2`. `lambda` holds the expected
+/// number of arrivals in each hour of the day, indexed from the hour `date1` falls on; each
+/// hour's count is Poisson and arrivals are spread uniformly within the hour.
+pub async fn sample_arrival_times(
+    date1: &str,
+    date2: &str,
+    lambda: Vec<f64>,
+) -> Result<Vec<DateTime<Utc>>, anyhow::Error> {
+    let mut rng = thread_rng();
+    let date_fmt = "%Y-%m-%d %H:%M:%S%:z"; // Ensure this format matches your input data
+    let d1 = DateTime::parse_from_str(date1, date_fmt)?;
+    let d2 = DateTime::parse_from_str(date2, date_fmt)?;
+    let poisson_dist_vec: Vec<Poisson<f64>> = create_poisson_distributions(lambda)?;
+    let mut arrivals: Vec<DateTime<Utc>> = Vec::new();
+    let mut curr_time = d1;
+    let mut hour = 0;
+    while curr_time < d2 {
+        let count = poisson_dist_vec[hour % poisson_dist_vec.len()].sample(&mut rng) as usize;
+        let mut offsets: Vec<i64> = (0..count).map(|_| rng.gen_range(0..3600)).collect();
+        offsets.sort();
+        for offset in offsets {
+            arrivals.push(to_utc_date_time(
+                curr_time + TimeDelta::try_seconds(offset).unwrap(),
+            ));
+        }
+        curr_time += TimeDelta::try_hours(1).unwrap();
+        hour += 1;
+    }
+    Ok(arrivals)
+}